@@ -24,7 +24,7 @@ use nom::{
 
 #[derive(Clone, Debug)]
 enum Value {
-    Char(char),
+    Char(#[allow(dead_code)] char),
     Number(u8),
     One,
     Two,
@@ -90,47 +90,67 @@ fn parse(input: &str) -> IResult<&str, Vec<Vec<Value>>> {
     )))(input)
 }
 
-fn parse_and_sum(
-    name: &str,
-    data: &str,
+// Returns (first value, first index, last value, last index) of the numbers of a line.
+// Each value is read from a single character, so the index is also the position in the line.
+fn first_and_last(
+    line: &[Value],
     extract_number: fn(&Value) -> Option<u8>,
-) {
-    // Parse the input date
-    let (_, result) = parse(data).finish().unwrap();
+) -> Option<(u8, usize, u8, usize)> {
+    let mut numbers = line
+        .iter()
+        .enumerate()
+        .filter_map(|(index, value)| extract_number(value).map(|number| (number, index)));
+
+    let (first_value, first_index) = numbers.next()?;
+    let (last_value, last_index) = numbers.next_back().unwrap_or((first_value, first_index));
 
-    let sum: u64 = result
+    Some((first_value, first_index, last_value, last_index))
+}
+
+fn calibration_sum(
+    lines: &[Vec<Value>],
+    extract_number: fn(&Value) -> Option<u8>,
+) -> u64 {
+    lines
         .iter()
         .map(|line| {
-            // Extract the numbers from the line
-            let numbers: Vec<_> = line.iter().filter_map(extract_number).collect();
-
             // Get the first and last number of the line
-            let first = numbers.first().map(|d| *d as u64).unwrap_or(0);
-            let last = numbers.last().map(|d| *d as u64).unwrap_or(0);
+            let (first, _, last, _) = first_and_last(line, extract_number).unwrap_or_default();
 
             // Return the number [first number][last number]
-            first * 10 + last
+            first as u64 * 10 + last as u64
         })
         // Sum all numbers
-        .sum();
+        .sum()
+}
 
-    println!("[{}] Sum is '{}'", name, sum)
+// Parses the input once, the parse already recognizes both digits and stringified numbers
+fn solve(data: &str) -> (u64, u64) {
+    // Parse the input date
+    let (_, lines) = parse(data).finish().unwrap();
+
+    (
+        // Do not care about stringified numbers
+        calibration_sum(&lines, Value::number),
+        // Handle stringified numbers
+        calibration_sum(&lines, Value::number_2),
+    )
 }
 
 fn first(
     name: &str,
     data: &str,
 ) {
-    // Do not care about stringified numbers
-    parse_and_sum(name, data, Value::number)
+    let (sum, _) = solve(data);
+    println!("[{}] Sum is '{}'", name, sum)
 }
 
 fn second(
     name: &str,
     data: &str,
 ) {
-    // Handle stringified numbers
-    parse_and_sum(name, data, Value::number_2)
+    let (_, sum) = solve(data);
+    println!("[{}] Sum is '{}'", name, sum)
 }
 
 pub fn run() {
@@ -139,3 +159,37 @@ pub fn run() {
     second("Second example", include_str!("data/day1/ex2")); // 302
     second("Second", include_str!("data/day1/input")); // 54591
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solve_both_parts_at_once() {
+        assert_eq!(solve(include_str!("data/day1/input")), (54573, 54591));
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        for data in [
+            include_str!("data/day1/ex1"),
+            include_str!("data/day1/ex2"),
+            include_str!("data/day1/input"),
+        ] {
+            let crlf = data.replace('\n', "\r\n");
+            assert_eq!(solve(&crlf), solve(data));
+        }
+
+        // Spelled numbers must not be read across a line ending: "twon" and "e9" hold no "one"
+        let (_, lines) = parse("twon\r\ne9\r\n").finish().unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            first_and_last(&lines[0], Value::number_2),
+            Some((2, 0, 2, 0))
+        );
+        assert_eq!(
+            first_and_last(&lines[1], Value::number_2),
+            Some((9, 1, 9, 1))
+        );
+    }
+}
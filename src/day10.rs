@@ -13,6 +13,7 @@ use nom::{
     Finish,
     IResult,
 };
+use std::collections::HashSet;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum Direction {
@@ -31,6 +32,19 @@ impl Direction {
             Direction::West => Direction::East,
         }
     }
+
+    fn next(
+        self,
+        coords: (i64, i64),
+    ) -> (i64, i64) {
+        let (x, y) = coords;
+        match self {
+            Direction::North => (x, y - 1),
+            Direction::South => (x, y + 1),
+            Direction::East => (x + 1, y),
+            Direction::West => (x - 1, y),
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -46,6 +60,26 @@ impl Pipe {
     ) -> Self {
         Pipe { first, second }
     }
+
+    // Box-drawing character of the pipe
+    fn symbol(&self) -> char {
+        let connects = |direction| self.first == direction || self.second == direction;
+
+        match (
+            connects(Direction::North),
+            connects(Direction::South),
+            connects(Direction::East),
+            connects(Direction::West),
+        ) {
+            (true, true, _, _) => '│',
+            (_, _, true, true) => '─',
+            (true, _, true, _) => '└',
+            (true, _, _, true) => '┘',
+            (_, true, true, _) => '┌',
+            (_, true, _, true) => '┐',
+            _ => '?',
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -191,53 +225,78 @@ impl Grid {
         )(input)
     }
 
-    fn main_loop(&self) -> Vec<(i64, i64)> {
-        let find_next_coords = |coords: (i64, i64), direction: Direction| {
-            let (x, y) = coords;
-            match direction {
-                Direction::North => (x, y - 1),
-                Direction::South => (x, y + 1),
-                Direction::East => (x + 1, y),
-                Direction::West => (x - 1, y),
+    // Draws the pipes of the loop, leaving every other tile blank
+    fn render_loop(
+        &self,
+        on_loop: &HashSet<(i64, i64)>,
+    ) -> String {
+        let mut rendered = String::new();
+        for (y, line) in self.tiles.tiles.iter().enumerate() {
+            for x in 0..line.len() {
+                let coords = (x as i64, y as i64);
+                match self.tiles.pipe_at(coords) {
+                    Some(pipe) if on_loop.contains(&coords) => rendered.push(pipe.symbol()),
+                    _ => rendered.push(' '),
+                }
             }
-        };
 
-        let mut visited = Vec::new();
-        visited.push(self.animal_position);
+            rendered.push('\n');
+        }
+
+        rendered
+    }
+
+    // Coordinates of the pipes of the main loop, for constant time lookups
+    fn loop_set(&self) -> HashSet<(i64, i64)> {
+        self.main_loop().map(|(coords, _)| coords).collect()
+    }
 
-        // Start at the animal position
+    fn main_loop(&self) -> MainLoop<'_> {
+        // Start at the animal position, taking the first direction of the pipe arbitrarily
         let start = self.tiles.pipe_at(self.animal_position).unwrap();
 
-        // We take the first direction of the pipe arbitrarily
-        let mut current_direction = start.first;
-        let mut current_coords = find_next_coords(self.animal_position, current_direction);
+        MainLoop {
+            tiles: &self.tiles,
+            start: self.animal_position,
+            next: Some((self.animal_position, start.first)),
+        }
+    }
+}
+
+// Walks the main loop, yielding each pipe coordinates and the direction taken to leave it,
+// until coming back to the animal position
+struct MainLoop<'a> {
+    tiles: &'a Tiles,
+    start: (i64, i64),
+    next: Option<((i64, i64), Direction)>,
+}
 
-        // And then we loop through the main loop until we come back to the animal position
-        loop {
-            if current_coords == self.animal_position {
-                break;
-            }
+impl Iterator for MainLoop<'_> {
+    type Item = ((i64, i64), Direction);
 
-            visited.push(current_coords);
+    fn next(&mut self) -> Option<Self::Item> {
+        let (current_coords, current_direction) = self.next?;
 
-            // (Unsafe) get of the current pipe
-            let current_pipe = self.tiles.pipe_at(current_coords).unwrap();
+        // Get the coordinates of the next pipe
+        let next_coords = current_direction.next(current_coords);
+
+        self.next = if next_coords == self.start {
+            None
+        } else {
+            // (Unsafe) get of the next pipe
+            let next_pipe = self.tiles.pipe_at(next_coords).unwrap();
 
             // Find the next direction based on the last direction taken
-            let next_direction = if current_pipe.first == current_direction.opposite() {
-                current_pipe.second
+            let next_direction = if next_pipe.first == current_direction.opposite() {
+                next_pipe.second
             } else {
-                current_pipe.first
+                next_pipe.first
             };
 
-            // Get the coordinates of the next pipe
-            let next = find_next_coords(current_coords, next_direction);
-
-            current_direction = next_direction;
-            current_coords = next;
-        }
+            Some((next_coords, next_direction))
+        };
 
-        visited
+        Some((current_coords, current_direction))
     }
 }
 
@@ -248,41 +307,53 @@ fn first(
     let (_, grid) = Grid::parse(data).finish().unwrap();
     // println!("[{}] {:#?}", name, grid);
 
-    let main_loop = grid.main_loop();
-
     // Furthest point is half of the main loop size
-    let furthest = main_loop.len() / 2;
+    let furthest = grid.main_loop().count() / 2;
 
     //println!("[{}] {:#?}", name, visited);
     println!("[{}] Furthest {:?}", name, furthest);
 }
 
+// Number of tiles enclosed by the loop, whatever the direction it is walked in
+fn enclosed_tiles(main_loop: &[(i64, i64)]) -> i64 {
+    // Shoelace algo, closing the loop on its first point
+    // Magic happening here
+    let sum = main_loop
+        .iter()
+        .zip(main_loop.iter().cycle().skip(1))
+        .map(|((x1, y1), (x2, y2))| x1 * y2 - y1 * x2)
+        .sum::<i64>()
+        .abs();
+
+    // Pick's theorem, the boundary being the loop itself
+    (sum - main_loop.len() as i64) / 2 + 1
+}
+
 fn second(
     name: &str,
     data: &str,
 ) {
     let (_, grid) = Grid::parse(data).finish().unwrap();
-    let mut main_loop = grid.main_loop();
+    let main_loop: Vec<_> = grid.main_loop().map(|(coords, _)| coords).collect();
 
-    // Shoelace algo
-    // Magic happening here
-    main_loop.push(grid.animal_position);
-    let sum = main_loop
-        .as_slice()
-        .windows(2)
-        .map(|window| {
-            let (x1, y1) = window[0];
-            let (x2, y2) = window[1];
-            x1 * y2 - y1 * x2
-        })
-        .sum::<i64>()
-        .abs();
-
-    let count = (sum - (main_loop.len() as i64 - 1)) / 2 + 1;
+    let count = enclosed_tiles(&main_loop);
 
     println!("[{}] Cells inside the loop: {}", name, count);
 }
 
+fn draw(
+    name: &str,
+    data: &str,
+) {
+    let (_, grid) = Grid::parse(data).finish().unwrap();
+
+    println!(
+        "[{}] Main loop:\n{}",
+        name,
+        grid.render_loop(&grid.loop_set())
+    );
+}
+
 pub fn run() {
     first("First example 1", include_str!("data/day10/ex1")); // 4
     first("First example 2", include_str!("data/day10/ex2")); // 8
@@ -290,4 +361,49 @@ pub fn run() {
     second("Second example 3", include_str!("data/day10/ex3")); // 10
     second("Second example 4", include_str!("data/day10/ex4")); // 8
     second("Second", include_str!("data/day10/input")); // 411
+    draw("Draw example 4", include_str!("data/day10/ex4"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enclosed_both_ways(data: &str) -> (i64, i64) {
+        let (_, grid) = Grid::parse(data).finish().unwrap();
+        let mut main_loop: Vec<_> = grid.main_loop().map(|(coords, _)| coords).collect();
+        let forward = enclosed_tiles(&main_loop);
+
+        main_loop.reverse();
+        (forward, enclosed_tiles(&main_loop))
+    }
+
+    #[test]
+    fn render_loop_skips_other_pipes() {
+        // The pipes in the corners are not part of the loop
+        let (_, grid) = Grid::parse("7...F\n.S-7.\n.|.|.\n.L-J.\nJ...L\n")
+            .finish()
+            .unwrap();
+        let on_loop = grid.loop_set();
+
+        assert_eq!(on_loop.len(), 8);
+        assert!(on_loop.contains(&(1, 1)));
+        assert!(!on_loop.contains(&(0, 0)));
+        assert!(!on_loop.contains(&(2, 2)));
+        assert_eq!(
+            grid.render_loop(&on_loop),
+            "     \n ┌─┐ \n │ │ \n └─┘ \n     \n"
+        );
+    }
+
+    #[test]
+    fn enclosed_tiles_of_example() {
+        assert_eq!(enclosed_both_ways(include_str!("data/day10/ex4")), (8, 8));
+    }
+
+    #[test]
+    fn enclosed_tiles_with_pinch() {
+        // The loop squeezes between pipes without enclosing the tiles at the bottom
+        let data = "..........\n.S------7.\n.|F----7|.\n.||....||.\n.||....||.\n.|L-7F-J|.\n.|..||..|.\n.L--JL--J.\n..........\n";
+        assert_eq!(enclosed_both_ways(data), (4, 4));
+    }
 }
@@ -6,7 +6,7 @@ use nom::{
     },
     combinator::{
         all_consuming,
-        map,
+        map_res,
         opt,
         value,
     },
@@ -20,7 +20,7 @@ use std::{
         Display,
         Formatter,
     },
-    ops::RangeInclusive,
+    ops::Range,
 };
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -36,13 +36,78 @@ impl Item {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RawSpace"))]
 struct Space {
     galaxies: Vec<(usize, usize)>,
+    // Dimensions of the grid, which may extend beyond the last galaxies
+    width: usize,
+    height: usize,
+}
+
+// Space as read from JSON, checked before becoming a `Space`
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct RawSpace {
+    galaxies: Vec<(usize, usize)>,
+    width: usize,
+    height: usize,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<RawSpace> for Space {
+    type Error = String;
+
+    fn try_from(raw: RawSpace) -> Result<Self, Self::Error> {
+        if raw.width == 0 || raw.height == 0 {
+            return Err(String::from("Empty space"));
+        }
+
+        let mut seen = std::collections::HashSet::new();
+        for &(x, y) in &raw.galaxies {
+            if x >= raw.width || y >= raw.height {
+                return Err(format!(
+                    "Galaxy ({}, {}) is outside of the {}x{} space",
+                    x, y, raw.width, raw.height
+                ));
+            }
+
+            if !seen.insert((x, y)) {
+                return Err(format!("Galaxy ({}, {}) appears twice", x, y));
+            }
+        }
+
+        Ok(Space {
+            galaxies: raw.galaxies,
+            width: raw.width,
+            height: raw.height,
+        })
+    }
 }
 
 impl Space {
-    fn new(items: Vec<Vec<Item>>) -> Self {
-        Space {
+    fn new(items: Vec<Vec<Item>>) -> Result<Self, String> {
+        let width = items.first().map(|line| line.len()).unwrap_or_default();
+        if width == 0 {
+            return Err(String::from("Empty space"));
+        }
+
+        if let Some((y, line)) = items
+            .iter()
+            .enumerate()
+            .find(|(_, line)| line.len() != width)
+        {
+            return Err(format!(
+                "Line {} has {} items, expected {}",
+                y,
+                line.len(),
+                width
+            ));
+        }
+
+        Ok(Space {
+            width,
+            height: items.len(),
             galaxies: items
                 .iter()
                 .enumerate()
@@ -55,25 +120,15 @@ impl Space {
                     })
                 })
                 .collect(),
-        }
+        })
     }
 
-    fn x_range(&self) -> RangeInclusive<usize> {
-        0..=(self
-            .galaxies
-            .iter()
-            .map(|(x, _)| *x)
-            .max()
-            .unwrap_or_default())
+    fn x_range(&self) -> Range<usize> {
+        0..self.width
     }
 
-    fn y_range(&self) -> RangeInclusive<usize> {
-        0..=(self
-            .galaxies
-            .iter()
-            .map(|(_, y)| *y)
-            .max()
-            .unwrap_or_default())
+    fn y_range(&self) -> Range<usize> {
+        0..self.height
     }
 
     fn expand(
@@ -99,12 +154,14 @@ impl Space {
             .x_range()
             .filter(|x| !self.galaxies.iter().any(|(gx, _)| gx == x))
             .collect();
+        let width = self.width + empty_cols.len() * (factor - 1);
         let x_expansions = expansions(empty_cols);
 
         let empty_rows: Vec<_> = self
             .y_range()
             .filter(|y| !self.galaxies.iter().any(|(_, gy)| gy == y))
             .collect();
+        let height = self.height + empty_rows.len() * (factor - 1);
         let y_expansions = expansions(empty_rows);
 
         let expanded = self
@@ -137,7 +194,11 @@ impl Space {
             })
             .collect();
 
-        Space { galaxies: expanded }
+        Space {
+            galaxies: expanded,
+            width,
+            height,
+        }
     }
 }
 
@@ -162,12 +223,83 @@ impl Display for Space {
 }
 
 fn parse(input: &str) -> IResult<&str, Space> {
-    all_consuming(map(
+    all_consuming(map_res(
         many1(terminated(many1(Item::parse), opt(line_ending))),
         Space::new,
     ))(input)
 }
 
+// Sum of the distances between every pair of galaxies, pair by pair
+fn sum_distances(space: &Space) -> i64 {
+    let mut sum = 0_i64;
+    for (i, (x1, y1)) in space.galaxies.iter().enumerate() {
+        for (x2, y2) in &space.galaxies[(i + 1)..] {
+            sum += (*x1 as i64 - *x2 as i64).abs() + (*y1 as i64 - *y2 as i64).abs();
+        }
+    }
+
+    sum
+}
+
+// Same sum, computed axis by axis on sorted coordinates
+// Once sorted, the i-th coordinate is added i times and subtracted (n - 1 - i) times
+fn sum_distances_fast(space: &Space) -> i64 {
+    let axis_sum = |mut coordinates: Vec<i64>| {
+        coordinates.sort_unstable();
+        let n = coordinates.len() as i64;
+        coordinates
+            .iter()
+            .enumerate()
+            .map(|(i, coordinate)| coordinate * (2 * i as i64 - n + 1))
+            .sum::<i64>()
+    };
+
+    axis_sum(space.galaxies.iter().map(|(x, _)| *x as i64).collect())
+        + axis_sum(space.galaxies.iter().map(|(_, y)| *y as i64).collect())
+}
+
+// Same sum, on the space before its expansion: each pair is as far apart as before, plus
+// `factor - 1` for each empty line between them
+fn sum_distances_lazy(
+    space: &Space,
+    factor: usize,
+) -> i64 {
+    let mut occupied_cols = vec![false; space.width];
+    let mut occupied_rows = vec![false; space.height];
+    for (x, y) in &space.galaxies {
+        occupied_cols[*x] = true;
+        occupied_rows[*y] = true;
+    }
+
+    // Number of empty lines before each index of an axis
+    let empty_before = |occupied: Vec<bool>| {
+        occupied
+            .into_iter()
+            .scan(0_i64, |empty, occupied| {
+                let before = *empty;
+                if !occupied {
+                    *empty += 1;
+                }
+                Some(before)
+            })
+            .collect::<Vec<_>>()
+    };
+    let empty_cols_before = empty_before(occupied_cols);
+    let empty_rows_before = empty_before(occupied_rows);
+
+    let mut sum = 0_i64;
+    for (i, (x1, y1)) in space.galaxies.iter().enumerate() {
+        for (x2, y2) in &space.galaxies[(i + 1)..] {
+            let distance = (*x1 as i64 - *x2 as i64).abs() + (*y1 as i64 - *y2 as i64).abs();
+            let crossed = (empty_cols_before[*x1] - empty_cols_before[*x2]).abs()
+                + (empty_rows_before[*y1] - empty_rows_before[*y2]).abs();
+            sum += distance + crossed * (factor as i64 - 1);
+        }
+    }
+
+    sum
+}
+
 fn do_stuff(
     name: &str,
     data: &str,
@@ -176,12 +308,32 @@ fn do_stuff(
     let (_, space) = parse(data).finish().unwrap();
     let expanded = space.expand(factor);
 
-    let mut sum = 0_i64;
-    for (i, (x1, y1)) in expanded.galaxies.iter().enumerate() {
-        for (x2, y2) in &expanded.galaxies[(i + 1)..] {
-            sum += (*x1 as i64 - *x2 as i64).abs() + (*y1 as i64 - *y2 as i64).abs();
-        }
-    }
+    let sum = sum_distances(&expanded);
+
+    println!("[{}] Sum of shortest paths: {}", name, sum);
+}
+
+fn do_stuff_fast(
+    name: &str,
+    data: &str,
+    factor: usize,
+) {
+    let (_, space) = parse(data).finish().unwrap();
+    let expanded = space.expand(factor);
+
+    let sum = sum_distances_fast(&expanded);
+
+    println!("[{}] Sum of shortest paths: {}", name, sum);
+}
+
+fn do_stuff_lazy(
+    name: &str,
+    data: &str,
+    factor: usize,
+) {
+    let (_, space) = parse(data).finish().unwrap();
+
+    let sum = sum_distances_lazy(&space, factor);
 
     println!("[{}] Sum of shortest paths: {}", name, sum);
 }
@@ -192,4 +344,89 @@ pub fn run() {
     do_stuff("Second example", include_str!("data/day11/ex1"), 10); // 1030
     do_stuff("Second example 2", include_str!("data/day11/ex1"), 100); // 8410
     do_stuff("Second", include_str!("data/day11/input"), 1000000); // 634 324 905 172
+    do_stuff_fast("Second fast", include_str!("data/day11/input"), 1000000); // 634 324 905 172
+    do_stuff_lazy("Second lazy", include_str!("data/day11/input"), 1000000); // 634 324 905 172
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn both_sums(
+        data: &str,
+        factor: usize,
+    ) -> (i64, i64) {
+        let (_, space) = parse(data).finish().unwrap();
+        let expanded = space.expand(factor);
+        (sum_distances(&expanded), sum_distances_fast(&expanded))
+    }
+
+    #[test]
+    fn expand_empty_space_beyond_galaxies() {
+        let (_, space) = parse("#..\n...\n..#\n...\n").finish().unwrap();
+        assert_eq!((space.width, space.height), (3, 4));
+
+        // The empty middle column and the empty rows, including the last one, are doubled
+        let expanded = space.expand(2);
+        assert_eq!((expanded.width, expanded.height), (4, 6));
+        assert_eq!(expanded.galaxies, vec![(0, 0), (3, 3)]);
+        assert_eq!(expanded.to_string(), "#...\n....\n....\n...#\n....\n....\n");
+    }
+
+    #[test]
+    fn ragged_or_empty_space_is_rejected() {
+        assert!(parse("#..\n..\n").is_err());
+        assert_eq!(Space::new(vec![]).err(), Some(String::from("Empty space")));
+    }
+
+    #[test]
+    fn fast_sum_matches_pairwise_sum() {
+        assert_eq!(both_sums(include_str!("data/day11/ex1"), 2), (374, 374));
+        assert_eq!(
+            both_sums(include_str!("data/day11/input"), 1000000),
+            (634324905172, 634324905172)
+        );
+    }
+
+    #[test]
+    fn lazy_sum_matches_expanded_sum() {
+        let (_, space) = parse(include_str!("data/day11/ex1")).finish().unwrap();
+
+        for (factor, expected) in [(1, 292), (2, 374), (10, 1030), (100, 8410)] {
+            assert_eq!(sum_distances(&space.expand(factor)), expected);
+            assert_eq!(sum_distances_lazy(&space, factor), expected);
+        }
+    }
+
+    #[test]
+    fn full_space_does_not_expand() {
+        // A galaxy in every row and column
+        let (_, space) = parse("#..\n..#\n.#.\n").finish().unwrap();
+
+        for factor in [1, 2, 1000000] {
+            let expanded = space.expand(factor);
+            assert_eq!(expanded.galaxies, space.galaxies);
+            assert_eq!((expanded.width, expanded.height), (3, 3));
+            assert_eq!(sum_distances_lazy(&space, factor), 2 + 3 + 3);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_round_trip() {
+        let (_, space) = parse(include_str!("data/day11/ex1")).finish().unwrap();
+
+        let json = serde_json::to_string(&space).unwrap();
+        let read: Space = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.galaxies, space.galaxies);
+        assert_eq!((read.width, read.height), (space.width, space.height));
+
+        // The same checks as when parsing the grid
+        let error = |json| serde_json::from_str::<Space>(json).unwrap_err().to_string();
+        assert!(error(r#"{"galaxies":[],"width":0,"height":1}"#).starts_with("Empty space"));
+        assert!(error(r#"{"galaxies":[[3,0]],"width":3,"height":1}"#)
+            .starts_with("Galaxy (3, 0) is outside of the 3x1 space"));
+        assert!(error(r#"{"galaxies":[[1,0],[1,0]],"width":3,"height":1}"#)
+            .starts_with("Galaxy (1, 0) appears twice"));
+    }
 }
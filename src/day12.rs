@@ -8,7 +8,7 @@ use nom::{
     },
     combinator::{
         all_consuming,
-        map,
+        map_res,
         opt,
         value,
     },
@@ -45,6 +45,9 @@ impl SpringState {
     }
 }
 
+// Arrangement counts, keyed by the remaining springs and damaged groups
+type ArrangementCache = HashMap<(Vec<SpringState>, Vec<u16>), usize>;
+
 #[derive(Debug)]
 struct Springs {
     states: Vec<SpringState>,
@@ -52,121 +55,251 @@ struct Springs {
 }
 
 impl Springs {
+    // An empty group cannot be told apart from no group at all, so it is rejected
+    fn new(
+        states: Vec<SpringState>,
+        damaged_groups: Vec<u16>,
+    ) -> Result<Self, String> {
+        if let Some(index) = damaged_groups.iter().position(|size| *size == 0) {
+            return Err(format!(
+                "Damaged group {} of {:?} has a size of 0",
+                index, damaged_groups
+            ));
+        }
+
+        Ok(Springs {
+            states,
+            damaged_groups,
+        })
+    }
+
     fn parse(input: &str) -> IResult<&str, Vec<Self>> {
         all_consuming(many1(terminated(
-            map(
+            map_res(
                 separated_pair(
                     many1(SpringState::parse),
                     space1,
                     separated_list1(char(','), character::complete::u16),
                 ),
-                |(states, damaged_groups)| {
-                    Springs {
-                        states,
-                        damaged_groups,
-                    }
-                },
+                |(states, damaged_groups)| Springs::new(states, damaged_groups),
             ),
             opt(line_ending),
         )))(input)
     }
 
-    fn find_arrangements(&self) -> usize {
-        fn run_loop(
-            states: &[SpringState],
-            damaged_groups: &[u16],
-            cache: &mut HashMap<(Vec<SpringState>, Vec<u16>), usize>,
-        ) -> usize {
-            let cache_key = (states.to_vec(), damaged_groups.to_vec());
-
-            // If the cache already has the value pre-computed, just return it
-            if let Some(count) = cache.get(&cache_key) {
-                *count
-            } else {
-                // Otherwise check the input variables
-                let result = match states.first() {
-                    // If we still have springs to consider...
-                    Some(state) => {
-                        match state {
-                            SpringState::Operational => {
-                                run_loop(&states[1..], damaged_groups, cache)
-                            }
+    // Unfolds as in the puzzle, copies being joined by an unknown spring
+    fn unfold(
+        &self,
+        copies: usize,
+    ) -> Self {
+        self.unfold_with(copies, Some(SpringState::Unknown))
+    }
 
-                            SpringState::Unknown => {
-                                // if the spring is unknown, it can either be operational...
-                                run_loop(&states[1..], damaged_groups, cache) +
-                                    // ... or damaged, in which case we just recurse swapping the first value by a damaged spring
-                                    run_loop(&[&[SpringState::Damaged], &states[1..]].concat(), damaged_groups, cache)
-                            }
+    // Repeats the springs, joining the copies with the `separator` spring, if any.
+    // The groups are simply repeated whatever the separator, even if it merges the last group of
+    // a copy with the first one of the next.
+    fn unfold_with(
+        &self,
+        copies: usize,
+        separator: Option<SpringState>,
+    ) -> Self {
+        let copy_len = self.states.len() + usize::from(separator.is_some());
+        Springs {
+            states: self
+                .states
+                .iter()
+                .copied()
+                .chain(separator)
+                .cycle()
+                .take((copy_len * copies).saturating_sub(usize::from(separator.is_some())))
+                .collect(),
+            damaged_groups: self
+                .damaged_groups
+                .iter()
+                .cycle()
+                .take(self.damaged_groups.len() * copies)
+                .copied()
+                .collect(),
+        }
+    }
 
-                            SpringState::Damaged => {
-                                match damaged_groups.first() {
-                                    None => {
-                                        // No more damaged springs, no solution
-                                        0
-                                    }
+    fn count_arrangements(
+        states: &[SpringState],
+        damaged_groups: &[u16],
+        cache: &mut ArrangementCache,
+    ) -> usize {
+        let cache_key = (states.to_vec(), damaged_groups.to_vec());
 
-                                    Some(first_group_size) => {
-                                        let first_group_size = *first_group_size as usize;
-
-                                        if
-                                        // Not enough springs left to fill the damaged group, no solution
-                                        states.len() < first_group_size ||
-                                            // There's at least one operational spring in the next 'first_group_size' springs, so the group is not possible
-                                            states[..first_group_size]
-                                                .iter()
-                                                .any(|state| *state == SpringState::Operational) ||
-                                            // The spring after the group size is damaged, which would created a group that is too big, so this is not possible
-                                            states
-                                                .get(first_group_size)
-                                                .is_some_and(|state| *state == SpringState::Damaged)
-                                        {
-                                            0
-                                        } else if states.len() == first_group_size {
-                                            // If there's only one group left and the remaining states are all damaged or unknown, then we have a solution
-                                            if damaged_groups.len() == 1 {
-                                                1
-                                            } else {
-                                                0
-                                            }
+        // If the cache already has the value pre-computed, just return it
+        if let Some(count) = cache.get(&cache_key) {
+            *count
+        } else {
+            // Otherwise check the input variables
+            let result = match states.first() {
+                // If we still have springs to consider...
+                Some(state) => {
+                    match state {
+                        SpringState::Operational => {
+                            Self::count_arrangements(&states[1..], damaged_groups, cache)
+                        }
+
+                        SpringState::Unknown => {
+                            // if the spring is unknown, it can either be operational...
+                            Self::count_arrangements(&states[1..], damaged_groups, cache) +
+                                // ... or damaged, in which case we just recurse swapping the first value by a damaged spring
+                                Self::count_arrangements(&[&[SpringState::Damaged], &states[1..]].concat(), damaged_groups, cache)
+                        }
+
+                        SpringState::Damaged => {
+                            match damaged_groups.first() {
+                                None => {
+                                    // No more damaged springs, no solution
+                                    0
+                                }
+
+                                Some(first_group_size) => {
+                                    let first_group_size = *first_group_size as usize;
+
+                                    if
+                                    // Not enough springs left to fill the damaged group, no solution
+                                    states.len() < first_group_size ||
+                                        // There's at least one operational spring in the next 'first_group_size' springs, so the group is not possible
+                                        states[..first_group_size]
+                                            .contains(&SpringState::Operational) ||
+                                        // The spring after the group size is damaged, which would created a group that is too big, so this is not possible
+                                        states
+                                            .get(first_group_size)
+                                            .is_some_and(|state| *state == SpringState::Damaged)
+                                    {
+                                        0
+                                    } else if states.len() == first_group_size {
+                                        // If there's only one group left and the remaining states are all damaged or unknown, then we have a solution
+                                        if damaged_groups.len() == 1 {
+                                            1
                                         } else {
-                                            run_loop(
-                                                &states[(first_group_size + 1)..],
-                                                &damaged_groups[1..],
-                                                cache,
-                                            )
+                                            0
                                         }
+                                    } else {
+                                        Self::count_arrangements(
+                                            &states[(first_group_size + 1)..],
+                                            &damaged_groups[1..],
+                                            cache,
+                                        )
                                     }
                                 }
                             }
                         }
                     }
+                }
 
-                    // ... else, if there's no more spring...
-                    None => {
-                        if damaged_groups.is_empty() {
-                            // ... and no more groups, then we have a solution...
-                            1
-                        } else {
-                            // ... otherwise this is not a solution since the arrangement does not have enough damaged springs
-                            0
-                        }
+                // ... else, if there's no more spring...
+                None => {
+                    if damaged_groups.is_empty() {
+                        // ... and no more groups, then we have a solution...
+                        1
+                    } else {
+                        // ... otherwise this is not a solution since the arrangement does not have enough damaged springs
+                        0
                     }
-                };
+                }
+            };
 
-                cache.insert(cache_key, result);
+            cache.insert(cache_key, result);
 
-                result
-            }
+            result
+        }
+    }
+
+    fn find_arrangements(&self) -> usize {
+        // Without any unknown spring, there's only one arrangement to check
+        if !self.states.contains(&SpringState::Unknown) {
+            let groups: Vec<_> = self
+                .states
+                .split(|state| *state == SpringState::Operational)
+                .filter(|group| !group.is_empty())
+                .map(|group| group.len() as u16)
+                .collect();
+
+            return usize::from(groups == self.damaged_groups);
         }
 
         let mut cache = HashMap::new();
-        run_loop(
+        Self::count_arrangements(
             self.states.as_slice(),
             self.damaged_groups.as_slice(),
             &mut cache,
         )
     }
+
+    // Rows share a single cache, so sub-problems common to several rows are only computed once.
+    // On the real input rows barely share any sub-problem, and the bigger cache makes this about
+    // twice as slow as a fresh cache per row, so it is only kept to check the counts.
+    #[cfg(test)]
+    fn find_arrangements_batch(rows: &[Springs]) -> Vec<usize> {
+        let mut cache = HashMap::new();
+        rows.iter()
+            .map(|row| {
+                Self::count_arrangements(
+                    row.states.as_slice(),
+                    row.damaged_groups.as_slice(),
+                    &mut cache,
+                )
+            })
+            .collect()
+    }
+
+    // Counts the arrangements by running the springs through an automaton built from the groups.
+    // For groups 1,1,3 the automaton reads `.#.#.###.`: each state is a position in this pattern,
+    // an operational state can loop on itself and the first and last states are optional.
+    fn find_arrangements_nfa(&self) -> usize {
+        let pattern: Vec<_> = once(SpringState::Operational)
+            .chain(self.damaged_groups.iter().flat_map(|size| {
+                (0..*size)
+                    .map(|_| SpringState::Damaged)
+                    .chain(once(SpringState::Operational))
+            }))
+            .collect();
+
+        // Number of paths ending in each state of the pattern
+        let mut counts = vec![0_usize; pattern.len()];
+        counts[0] = 1;
+
+        for state in &self.states {
+            let mut next_counts = vec![0_usize; pattern.len()];
+            for (index, count) in counts.iter().enumerate().filter(|(_, count)| **count > 0) {
+                for spring in [SpringState::Operational, SpringState::Damaged] {
+                    if *state != SpringState::Unknown && *state != spring {
+                        continue;
+                    }
+
+                    let next_index = if pattern[index] == SpringState::Operational
+                        && spring == SpringState::Operational
+                    {
+                        // Operational springs can repeat between groups
+                        Some(index)
+                    } else {
+                        // Otherwise the spring must match the next position of the pattern
+                        Some(index + 1)
+                            .filter(|next_index| pattern.get(*next_index) == Some(&spring))
+                    };
+
+                    if let Some(next_index) = next_index {
+                        next_counts[next_index] += count;
+                    }
+                }
+            }
+
+            counts = next_counts;
+        }
+
+        // Accept when ending after the last group, with or without trailing operational springs
+        let last = pattern.len() - 1;
+        if last == 0 {
+            counts[last]
+        } else {
+            counts[last] + counts[last - 1]
+        }
+    }
 }
 
 fn first(
@@ -189,30 +322,22 @@ fn second(
     let (_, springs) = Springs::parse(data).finish().unwrap();
 
     let total: usize = springs
-        .into_iter()
-        .map(
-            |Springs {
-                 states,
-                 damaged_groups,
-             }| {
-                Springs {
-                    states: states
-                        .iter()
-                        .chain(once(&SpringState::Unknown))
-                        .cycle()
-                        .take((states.len() + 1) * 5 - 1)
-                        .cloned()
-                        .collect(),
-                    damaged_groups: damaged_groups
-                        .iter()
-                        .cycle()
-                        .take(damaged_groups.len() * 5)
-                        .copied()
-                        .collect(),
-                }
-            },
-        )
-        .map(|springs| springs.find_arrangements())
+        .iter()
+        .map(|springs| springs.unfold(5).find_arrangements())
+        .sum();
+
+    println!("[{}] Possible arrangements: {:#?}", name, total);
+}
+
+fn second_nfa(
+    name: &str,
+    data: &str,
+) {
+    let (_, springs) = Springs::parse(data).finish().unwrap();
+
+    let total: usize = springs
+        .iter()
+        .map(|springs| springs.unfold(5).find_arrangements_nfa())
         .sum();
 
     println!("[{}] Possible arrangements: {:#?}", name, total);
@@ -223,4 +348,108 @@ pub fn run() {
     first("First", include_str!("data/day12/input")); // 7407
     second("Second example", include_str!("data/day12/ex1")); // 525 152
     second("Second", include_str!("data/day12/input")); // 30 568 243 604 962
+    second_nfa("Second example NFA", include_str!("data/day12/ex1")); // 525 152
+    second_nfa("Second NFA", include_str!("data/day12/input")); // 30 568 243 604 962
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nfa_matches_recursive_count() {
+        let (_, springs) = Springs::parse(include_str!("data/day12/ex1"))
+            .finish()
+            .unwrap();
+
+        for springs in springs {
+            assert_eq!(springs.find_arrangements_nfa(), springs.find_arrangements());
+
+            let unfolded = springs.unfold(5);
+            assert_eq!(
+                unfolded.find_arrangements_nfa(),
+                unfolded.find_arrangements()
+            );
+        }
+    }
+
+    #[test]
+    fn shared_cache_matches_fresh_caches() {
+        for (data, total) in [
+            (include_str!("data/day12/ex1"), 525152),
+            (include_str!("data/day12/input"), 30568243604962),
+        ] {
+            let (_, springs) = Springs::parse(data).finish().unwrap();
+            let unfolded: Vec<_> = springs.iter().map(|springs| springs.unfold(5)).collect();
+
+            let batch = Springs::find_arrangements_batch(&unfolded);
+            let single: Vec<_> = unfolded
+                .iter()
+                .map(|springs| springs.find_arrangements())
+                .collect();
+            assert_eq!(batch, single);
+            assert_eq!(batch.iter().sum::<usize>(), total);
+        }
+    }
+
+    #[test]
+    fn unfold_with_other_separators() {
+        let (_, springs) = Springs::parse(".??..??...?##. 1,1,3\n???.### 1,1,3\n")
+            .finish()
+            .unwrap();
+        let count =
+            |springs: &Springs, separator| springs.unfold_with(5, separator).find_arrangements();
+
+        assert_eq!(springs[0].find_arrangements(), 4);
+        assert_eq!(count(&springs[0], Some(SpringState::Unknown)), 16384);
+        // Each copy is on its own, with the same arrangements
+        assert_eq!(
+            count(&springs[0], Some(SpringState::Operational)),
+            4 * 4 * 4 * 4 * 4
+        );
+        // The copies already start and end with operational springs
+        assert_eq!(count(&springs[0], None), 1024);
+
+        assert_eq!(count(&springs[1], Some(SpringState::Unknown)), 1);
+        assert_eq!(count(&springs[1], Some(SpringState::Operational)), 1);
+        // Without separator, the second copy starts right after the last group of the first one
+        assert_eq!(count(&springs[1], None), 0);
+
+        let unfolded = springs[0].unfold_with(2, None);
+        assert_eq!(unfolded.states.len(), 28);
+        assert_eq!(unfolded.damaged_groups, vec![1, 1, 3, 1, 1, 3]);
+        assert_eq!(
+            unfolded.find_arrangements_nfa(),
+            unfolded.find_arrangements()
+        );
+    }
+
+    #[test]
+    fn empty_group_is_rejected() {
+        assert!(Springs::parse("??? 0,1\n").is_err());
+        assert_eq!(
+            Springs::new(vec![SpringState::Unknown; 3], vec![0, 1]).err(),
+            Some(String::from("Damaged group 0 of [0, 1] has a size of 0"))
+        );
+    }
+
+    #[test]
+    fn known_springs_have_at_most_one_arrangement() {
+        let (_, springs) = Springs::parse(
+            "#.#.### 1,1,3
+.#.###.#.###### 1,3,1,6
+#.#.### 1,1,2
+..## 1
+.... 1
+",
+        )
+        .finish()
+        .unwrap();
+
+        let arrangements: Vec<_> = springs
+            .iter()
+            .map(|springs| springs.find_arrangements())
+            .collect();
+        assert_eq!(arrangements, vec![1, 1, 0, 0, 0]);
+    }
 }
@@ -6,7 +6,7 @@ use nom::{
     },
     combinator::{
         all_consuming,
-        map,
+        map_res,
         opt,
         value,
     },
@@ -15,6 +15,7 @@ use nom::{
     Finish,
     IResult,
 };
+use rayon::prelude::*;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum Item {
@@ -28,7 +29,7 @@ impl Item {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum ReflectionAxis {
     Horizontal(usize),
     Vertical(usize),
@@ -40,82 +41,145 @@ struct Map {
 }
 
 impl Map {
+    // The reflection axis search expects every row to have the same width
+    fn new(items: Vec<Vec<Item>>) -> Result<Self, String> {
+        let width = items.first().map(|row| row.len()).unwrap_or_default();
+        if let Some((index, row)) = items.iter().enumerate().find(|(_, row)| row.len() != width) {
+            return Err(format!(
+                "Row {} has {} items, expected {}",
+                index,
+                row.len(),
+                width
+            ));
+        }
+
+        Ok(Map { items })
+    }
+
     fn parse(input: &str) -> IResult<&str, Self> {
-        map(
-            many1(terminated(many1(Item::parse), line_ending)),
-            |items| Map { items },
+        map_res(
+            // The last line of the last map may not be followed by a newline
+            many1(terminated(many1(Item::parse), opt(line_ending))),
+            Map::new,
         )(input)
     }
 
+    // Items differing from their reflection across the axis before `index`, lazily, as the line
+    // and the position of the item on the left side of the axis
+    fn differences(
+        items: &[Vec<Item>],
+        index: usize,
+    ) -> impl Iterator<Item = (usize, usize)> + '_ {
+        items
+            .iter()
+            .enumerate()
+            .flat_map(move |(line_index, line)| {
+                let size = index.min(line.len() - index);
+
+                line[(index - size)..index]
+                    .iter()
+                    .zip((line[index..(index + size)]).iter().rev())
+                    .enumerate()
+                    .filter(|(_, (l, r))| **l != **r)
+                    .map(move |(offset, _)| (line_index, index - size + offset))
+            })
+    }
+
+    // Finds an axis with exactly `smudges` differences between both sides
     fn find_reflection_axis(
         items: &[Vec<Item>],
-        cond: fn(usize) -> bool,
+        smudges: usize,
     ) -> Option<usize> {
         (1..(items[0].len())).find(|index| {
-            let index = *index;
-            let differences: usize = items
-                .iter()
-                .map(|line| {
-                    let size = index.min(line.len() - index);
-
-                    let diff = &line[(index - size)..index]
-                        .iter()
-                        .zip((line[index..(index + size)]).iter().rev())
-                        .filter(|(l, r)| **l != **r)
-                        .count();
-
-                    *diff
-                })
-                .sum();
-
-            cond(differences)
+            // Stop counting as soon as there are more differences than smudges
+            Self::differences(items, *index).take(smudges + 1).count() == smudges
         })
     }
 
+    // Columns of the map as lines
+    fn transposed(&self) -> Vec<Vec<Item>> {
+        let width = self
+            .items
+            .first()
+            .map(|line| line.len())
+            .unwrap_or_default();
+        let mut transposed = (0..width).map(|_| vec![]).collect::<Vec<_>>();
+
+        for line in &self.items {
+            for (item, transposed_row) in line.iter().zip(&mut transposed) {
+                transposed_row.push(*item);
+            }
+        }
+
+        transposed
+    }
+
     fn reflection(
         &self,
-        cond: fn(usize) -> bool,
+        smudges: usize,
     ) -> Option<ReflectionAxis> {
-        Self::find_reflection_axis(&self.items, cond)
+        Self::find_reflection_axis(&self.items, smudges)
             .map(ReflectionAxis::Vertical)
             .or_else(|| {
-                if let Some(first) = &self.items.first() {
-                    let mut transposed = (0..first.len()).map(|_| vec![]).collect::<Vec<_>>();
-
-                    for line in &self.items {
-                        for (item, transposed_row) in line.iter().zip(&mut transposed) {
-                            transposed_row.push(*item);
-                        }
-                    }
-
-                    Self::find_reflection_axis(&transposed, cond).map(ReflectionAxis::Horizontal)
-                } else {
+                if self.items.is_empty() {
                     None
+                } else {
+                    Self::find_reflection_axis(&self.transposed(), smudges)
+                        .map(ReflectionAxis::Horizontal)
                 }
             })
     }
+
+    // Position (x, y) of the smudge creating the part two reflection, taken on the left of a
+    // vertical axis or above a horizontal one, as fixing either side works
+    fn smudge_location(&self) -> Option<(usize, usize)> {
+        match self.reflection(1)? {
+            ReflectionAxis::Vertical(axis) => {
+                Self::differences(&self.items, axis)
+                    .next()
+                    .map(|(y, x)| (x, y))
+            }
+            ReflectionAxis::Horizontal(axis) => Self::differences(&self.transposed(), axis).next(),
+        }
+    }
 }
 
 fn parse(input: &str) -> IResult<&str, Vec<Map>> {
     all_consuming(many1(terminated(Map::parse, opt(line_ending))))(input)
 }
 
+// Reflection axis of each map along with its contribution to the total, in the maps order
+// Maps without any reflection are `None`, so that each entry still matches its map
+// Maps are independent from each others, so they are searched in parallel, keeping their order
+fn summarize(
+    data: &str,
+    smudges: usize,
+) -> Vec<Option<(ReflectionAxis, usize)>> {
+    let (_, maps) = parse(data).finish().unwrap();
+
+    maps.par_iter()
+        .map(|map| {
+            map.reflection(smudges).map(|reflection| {
+                let score = match reflection {
+                    ReflectionAxis::Horizontal(axis) => axis * 100,
+                    ReflectionAxis::Vertical(axis) => axis,
+                };
+
+                (reflection, score)
+            })
+        })
+        .collect()
+}
+
 fn find_reflections(
     name: &str,
     data: &str,
-    cond: fn(usize) -> bool,
+    smudges: usize,
 ) {
-    let (_, maps) = parse(data).finish().unwrap();
-
-    let total: usize = maps
+    let total: usize = summarize(data, smudges)
         .iter()
-        .filter_map(|map| map.reflection(cond))
-        .map(|reflection| {
-            match reflection {
-                ReflectionAxis::Horizontal(axis) => axis * 100,
-                ReflectionAxis::Vertical(axis) => axis,
-            }
-        })
+        .flatten()
+        .map(|(_, score)| score)
         .sum();
 
     println!("[{}] Total: {:?}", name, total);
@@ -125,14 +189,24 @@ fn first(
     name: &str,
     data: &str,
 ) {
-    find_reflections(name, data, |differences| differences == 0);
+    find_reflections(name, data, 0);
 }
 
 fn second(
     name: &str,
     data: &str,
 ) {
-    find_reflections(name, data, |differences| differences == 1);
+    find_reflections(name, data, 1);
+}
+
+fn smudges(
+    name: &str,
+    data: &str,
+) {
+    let (_, maps) = parse(data).finish().unwrap();
+    let locations: Vec<_> = maps.iter().map(|map| map.smudge_location()).collect();
+
+    println!("[{}] Smudges: {:?}", name, locations);
 }
 
 pub fn run() {
@@ -140,4 +214,83 @@ pub fn run() {
     first("First", include_str!("data/day13/input")); // 27 505
     second("Second example", include_str!("data/day13/ex1")); // 405
     second("Second", include_str!("data/day13/input")); // 22 906
+    smudges("Smudges example", include_str!("data/day13/ex1"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_without_trailing_newline() {
+        let data = "#.##..##.\n..#.##.#.\n##......#\n##......#\n..#.##.#.\n..##..##.\n#.#.##.#.\n\n#...##..#\n#....#..#\n..##..###\n#####.##.\n#####.##.\n..##..###\n#....#..#";
+
+        let (_, maps) = parse(data).finish().unwrap();
+        assert_eq!(maps.len(), 2);
+        assert_eq!(maps[1].items.len(), 7);
+
+        let reflections: Vec<_> = maps.iter().filter_map(|map| map.reflection(0)).collect();
+        assert!(matches!(reflections[0], ReflectionAxis::Vertical(5)));
+        assert!(matches!(reflections[1], ReflectionAxis::Horizontal(4)));
+    }
+
+    #[test]
+    fn ragged_map_is_rejected() {
+        let data = "#.##..\n..#.##.#.\n##......#\n";
+
+        assert!(parse(data).is_err());
+        assert_eq!(
+            Map::new(vec![vec![Item::Ash; 3], vec![Item::Rock; 2]]).err(),
+            Some(String::from("Row 1 has 2 items, expected 3"))
+        );
+    }
+
+    #[test]
+    fn summarize_example() {
+        let data = include_str!("data/day13/ex1");
+
+        assert_eq!(
+            summarize(data, 0),
+            vec![
+                Some((ReflectionAxis::Vertical(5), 5)),
+                Some((ReflectionAxis::Horizontal(4), 400))
+            ]
+        );
+        assert_eq!(
+            summarize(data, 1),
+            vec![
+                Some((ReflectionAxis::Horizontal(3), 300)),
+                Some((ReflectionAxis::Horizontal(1), 100))
+            ]
+        );
+    }
+
+    #[test]
+    fn summarize_keeps_maps_without_reflection() {
+        let data = "#.##..##.\n..#.##.#.\n##......#\n##......#\n..#.##.#.\n..##..##.\n#.#.##.#.\n\n#.\n.#\n\n#...##..#\n#....#..#\n..##..###\n#####.##.\n#####.##.\n..##..###\n#....#..#\n";
+
+        assert_eq!(
+            summarize(data, 0),
+            vec![
+                Some((ReflectionAxis::Vertical(5), 5)),
+                None,
+                Some((ReflectionAxis::Horizontal(4), 400))
+            ]
+        );
+    }
+
+    #[test]
+    fn smudge_locations_of_example() {
+        let (_, maps) = parse(include_str!("data/day13/ex1")).finish().unwrap();
+
+        // Top left, reflected in the sixth row
+        assert_eq!(maps[0].smudge_location(), Some((0, 0)));
+        // Fifth column of the first row, reflected in the second row
+        assert_eq!(maps[1].smudge_location(), Some((4, 0)));
+
+        // Fixing the smudge makes the part two axis a perfect reflection
+        let mut fixed = Map::new(maps[0].items.clone()).unwrap();
+        fixed.items[0][0] = Item::Ash;
+        assert_eq!(Map::differences(&fixed.transposed(), 3).count(), 0);
+    }
 }
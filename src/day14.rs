@@ -1,19 +1,19 @@
 use nom::{
-    branch::alt,
     character::complete::{
-        char,
+        anychar,
         line_ending,
     },
     combinator::{
         all_consuming,
         map,
-        value,
+        map_opt,
     },
     multi::many1,
     sequence::terminated,
     Finish,
     IResult,
 };
+use rayon::prelude::*;
 use std::{
     collections::{
         hash_map::DefaultHasher,
@@ -37,155 +37,167 @@ enum Item {
 }
 
 impl Item {
+    fn from_char(c: char) -> Option<Self> {
+        match c {
+            'O' => Some(Item::RoundedRock),
+            '#' => Some(Item::CubeRock),
+            '.' => Some(Item::Empty),
+            _ => None,
+        }
+    }
+
     fn parse(input: &str) -> IResult<&str, Self> {
-        alt((
-            value(Item::RoundedRock, char('O')),
-            value(Item::CubeRock, char('#')),
-            value(Item::Empty, char('.')),
-        ))(input)
+        map_opt(anychar, Item::from_char)(input)
     }
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Direction {
+    North,
+    West,
+    South,
+    East,
+}
+
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 struct Map {
     items: Vec<Vec<Item>>,
 }
 
 impl Map {
+    fn from_items(items: Vec<Vec<Item>>) -> Self {
+        Map { items }
+    }
+
     fn parse(input: &str) -> IResult<&str, Self> {
         all_consuming(map(
             many1(terminated(many1(Item::parse), line_ending)),
-            |items| Map { items },
+            Map::from_items,
         ))(input)
     }
 
-    fn tilt_north(&mut self) -> &mut Self {
-        match self.items.first() {
-            None => self,
-            Some(first) => {
-                let first_line_len = first.len();
-
-                for x in 0..first_line_len {
-                    let mut last_fixed_item_y: Option<usize> = None;
-                    for y in 0..self.items.len() {
-                        let item = self.items[y][x];
-                        match item {
-                            Item::RoundedRock => {
-                                let new_y = last_fixed_item_y.map(|y| y + 1).unwrap_or_default();
-
-                                self.items[y][x] = Item::Empty;
-                                self.items[new_y][x] = Item::RoundedRock;
-
-                                last_fixed_item_y = Some(new_y);
-                            }
-                            Item::CubeRock => {
-                                last_fixed_item_y = Some(y);
-                            }
-                            Item::Empty => {}
-                        }
-                    }
-                }
+    // Rolls the rounded rocks of a line towards its start, until they hit a cube rock or another
+    // rounded rock
+    fn roll_to_start(line: &mut [Item]) {
+        let mut last_fixed_item: Option<usize> = None;
+        for index in 0..line.len() {
+            match line[index] {
+                Item::RoundedRock => {
+                    let new_index = last_fixed_item.map(|i| i + 1).unwrap_or_default();
+
+                    line[index] = Item::Empty;
+                    line[new_index] = Item::RoundedRock;
 
-                self
+                    last_fixed_item = Some(new_index);
+                }
+                Item::CubeRock => {
+                    last_fixed_item = Some(index);
+                }
+                Item::Empty => {}
             }
         }
     }
 
-    fn tilt_west(&mut self) -> &mut Self {
-        for y in 0..self.items.len() {
-            let mut last_fixed_item_x: Option<usize> = None;
-            for x in 0..self.items[y].len() {
-                let item = self.items[y][x];
-                match item {
-                    Item::RoundedRock => {
-                        let new_x = last_fixed_item_x.map(|x| x + 1).unwrap_or_default();
+    fn roll_to_end(line: &mut [Item]) {
+        line.reverse();
+        Self::roll_to_start(line);
+        line.reverse();
+    }
 
-                        self.items[y][x] = Item::Empty;
-                        self.items[y][new_x] = Item::RoundedRock;
+    // Lines are independent from each others, so they are rolled in parallel
+    fn roll_rows(
+        &mut self,
+        roll: fn(&mut [Item]),
+    ) -> &mut Self {
+        self.items.par_iter_mut().for_each(|line| roll(line));
+        self
+    }
 
-                        last_fixed_item_x = Some(new_x);
-                    }
-                    Item::CubeRock => {
-                        last_fixed_item_x = Some(x);
-                    }
-                    Item::Empty => {}
-                }
+    // Columns are copied out of the rows, rolled in parallel, then written back
+    fn roll_columns(
+        &mut self,
+        roll: fn(&mut [Item]),
+    ) -> &mut Self {
+        let width = self
+            .items
+            .first()
+            .map(|line| line.len())
+            .unwrap_or_default();
+        let columns: Vec<Vec<Item>> = (0..width)
+            .into_par_iter()
+            .map(|x| {
+                let mut column: Vec<_> = self.items.iter().map(|line| line[x]).collect();
+                roll(&mut column);
+                column
+            })
+            .collect();
+
+        for (x, column) in columns.into_iter().enumerate() {
+            for (line, item) in self.items.iter_mut().zip(column) {
+                line[x] = item;
             }
         }
 
         self
     }
 
-    fn tilt_south(&mut self) -> &mut Self {
-        match self.items.first() {
-            None => self,
-            Some(first) => {
-                let first_line_len = first.len();
-
-                for x in 0..first_line_len {
-                    let mut last_fixed_item_y: Option<usize> = None;
-                    for y in (0..self.items.len()).rev() {
-                        let item = self.items[y][x];
-                        match item {
-                            Item::RoundedRock => {
-                                let new_y = last_fixed_item_y
-                                    .map(|y| y - 1)
-                                    .unwrap_or(self.items.len() - 1);
-
-                                self.items[y][x] = Item::Empty;
-                                self.items[new_y][x] = Item::RoundedRock;
-
-                                last_fixed_item_y = Some(new_y);
-                            }
-                            Item::CubeRock => {
-                                last_fixed_item_y = Some(y);
-                            }
-                            Item::Empty => {}
-                        }
-                    }
-                }
+    fn tilt_north(&mut self) -> &mut Self {
+        self.roll_columns(Self::roll_to_start)
+    }
 
-                self
-            }
-        }
+    fn tilt_west(&mut self) -> &mut Self {
+        self.roll_rows(Self::roll_to_start)
+    }
+
+    fn tilt_south(&mut self) -> &mut Self {
+        self.roll_columns(Self::roll_to_end)
     }
 
     fn tilt_east(&mut self) -> &mut Self {
-        for y in 0..self.items.len() {
-            let mut last_fixed_item_x: Option<usize> = None;
-            for x in (0..self.items[y].len()).rev() {
-                let item = self.items[y][x];
-                match item {
-                    Item::RoundedRock => {
-                        let new_x = last_fixed_item_x
-                            .map(|x| x - 1)
-                            .unwrap_or(self.items[y].len() - 1);
+        self.roll_rows(Self::roll_to_end)
+    }
 
-                        self.items[y][x] = Item::Empty;
-                        self.items[y][new_x] = Item::RoundedRock;
+    // Hash of the map state, used to detect cycles
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
 
-                        last_fixed_item_x = Some(new_x);
-                    }
-                    Item::CubeRock => {
-                        last_fixed_item_x = Some(x);
-                    }
-                    Item::Empty => {}
-                }
-            }
+    fn tilt(
+        &mut self,
+        direction: Direction,
+    ) -> &mut Self {
+        match direction {
+            Direction::North => self.tilt_north(),
+            Direction::West => self.tilt_west(),
+            Direction::South => self.tilt_south(),
+            Direction::East => self.tilt_east(),
         }
-
-        self
     }
 
-    fn load(&self) -> usize {
+    // Load on the beams of the edge the rocks were tilted to,
+    // each rounded rock weighting its distance from the opposite edge
+    fn load_for(
+        &self,
+        direction: Direction,
+    ) -> usize {
         let lines = self.items.len();
         self.items
             .iter()
             .enumerate()
             .flat_map(|(y, line)| {
-                line.iter().filter_map(move |item| {
+                let columns = line.len();
+                line.iter().enumerate().filter_map(move |(x, item)| {
                     match item {
-                        Item::RoundedRock => Some(lines - y),
+                        Item::RoundedRock => {
+                            Some(match direction {
+                                Direction::North => lines - y,
+                                Direction::West => columns - x,
+                                Direction::South => y + 1,
+                                Direction::East => x + 1,
+                            })
+                        }
                         Item::CubeRock => None,
                         Item::Empty => None,
                     }
@@ -193,6 +205,10 @@ impl Map {
             })
             .sum()
     }
+
+    fn load(&self) -> usize {
+        self.load_for(Direction::North)
+    }
 }
 
 impl Display for Map {
@@ -225,31 +241,57 @@ fn first(
     println!("[{}] Load: {}", name, tilted.load());
 }
 
-fn second(
-    name: &str,
+// Spins the map until its state repeats with a period landing on the last spin, returning the
+// number of spins before the first repeated state, the length of the cycle and the final load.
+// When `record_loads` is set, the load after each spin is kept as well, which makes the period
+// visible
+fn spin(
     data: &str,
-) {
+    record_loads: bool,
+) -> ((usize, usize, usize), Vec<usize>) {
     let (_, mut map) = Map::parse(data).finish().unwrap();
 
     let mut tilted = &mut map;
     let mut seen = HashMap::new();
+    let mut loads = Vec::new();
+    let mut cycle = None;
 
     for i in 0..1000000000 {
-        let mut hasher = DefaultHasher::new();
-        tilted.hash(&mut hasher);
-        let hash = hasher.finish();
-
         // Cycle detection
-        if let Some(seen_at) = seen.insert(hash, i) {
-            if (1000000000 - i) % (i - seen_at) == 0 {
+        if let Some(seen_at) = seen.insert(tilted.fingerprint(), i) {
+            let (_, cycle_len) = *cycle.get_or_insert((seen_at, i - seen_at));
+            if (1000000000 - i) % cycle_len == 0 {
                 break;
             }
         }
 
-        tilted = tilted.tilt_north().tilt_west().tilt_south().tilt_east();
+        for direction in [
+            Direction::North,
+            Direction::West,
+            Direction::South,
+            Direction::East,
+        ] {
+            tilted = tilted.tilt(direction);
+        }
+
+        if record_loads {
+            loads.push(tilted.load());
+        }
     }
 
-    println!("[{}] Load: {}", name, tilted.load());
+    let (cycle_start, cycle_len) = cycle.unwrap_or((0, 0));
+    ((cycle_start, cycle_len, tilted.load()), loads)
+}
+
+fn second(
+    name: &str,
+    data: &str,
+) {
+    let ((cycle_start, cycle_len, load), _) = spin(data, false);
+    println!(
+        "[{}] Load: {} (cycle of {} spins after {} spins)",
+        name, load, cycle_len, cycle_start
+    );
 }
 
 pub fn run() {
@@ -258,3 +300,112 @@ pub fn run() {
     second("Second example", include_str!("data/day14/ex1")); // 64
     second("Second", include_str!("data/day14/input")); // 99 118
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Item::*,
+        *,
+    };
+
+    #[test]
+    fn tilt_from_items() {
+        let mut map = Map::from_items(vec![
+            vec![Empty, RoundedRock, CubeRock, Empty, RoundedRock],
+            vec![RoundedRock, Empty, Empty, Empty, Empty],
+        ]);
+
+        map.tilt_west();
+        assert_eq!(
+            map,
+            Map::from_items(vec![
+                vec![RoundedRock, Empty, CubeRock, RoundedRock, Empty],
+                vec![RoundedRock, Empty, Empty, Empty, Empty],
+            ])
+        );
+
+        map.tilt_east();
+        assert_eq!(
+            map.items[0],
+            vec![Empty, RoundedRock, CubeRock, Empty, RoundedRock]
+        );
+        assert_eq!(Item::from_char('O'), Some(RoundedRock));
+        assert_eq!(Item::from_char('x'), None);
+    }
+
+    #[test]
+    fn load_after_tilt_in_each_direction() {
+        // O . #
+        // . O .
+        // O . .
+        let map = Map::from_items(vec![
+            vec![RoundedRock, Empty, CubeRock],
+            vec![Empty, RoundedRock, Empty],
+            vec![RoundedRock, Empty, Empty],
+        ]);
+
+        let load_after = |direction| map.clone().tilt(direction).load_for(direction);
+        assert_eq!(load_after(Direction::North), 3 + 3 + 2);
+        assert_eq!(load_after(Direction::West), 3 + 3 + 3);
+        assert_eq!(load_after(Direction::South), 2 + 3 + 3);
+        assert_eq!(load_after(Direction::East), 2 + 3 + 3);
+        assert_eq!(map.load(), map.load_for(Direction::North));
+    }
+
+    #[test]
+    fn equal_maps_have_equal_fingerprints() {
+        let (_, parsed) = Map::parse("O.#\n.O.\n").finish().unwrap();
+        let built = Map::from_items(vec![
+            vec![RoundedRock, Empty, CubeRock],
+            vec![Empty, RoundedRock, Empty],
+        ]);
+        assert_eq!(parsed.fingerprint(), built.fingerprint());
+
+        let mut tilted = built.clone();
+        tilted.tilt_north();
+        assert_ne!(tilted.fingerprint(), built.fingerprint());
+    }
+
+    #[test]
+    fn loads_repeat_with_the_cycle() {
+        let ((_, _, load), loads) = spin(include_str!("data/day14/ex1"), true);
+
+        // The example settles in a cycle of 7 spins after 3 spins, and stops spinning once the
+        // remaining spins are a multiple of the period
+        assert_eq!(
+            loads,
+            vec![87, 69, 69, 69, 65, 64, 65, 63, 68, 69, 69, 65, 64]
+        );
+        assert_eq!(loads[2..6], loads[9..13]);
+        assert_eq!(load, 64);
+        assert_eq!(
+            spin(include_str!("data/day14/ex1"), false),
+            ((3, 7, 64), Vec::new())
+        );
+    }
+
+    #[test]
+    fn detected_cycle_matches_brute_force() {
+        let data = include_str!("data/day14/ex1");
+        let ((cycle_start, cycle_len, _), _) = spin(data, false);
+
+        // Spin a few thousand times and look for the first state seen before
+        let (_, mut map) = Map::parse(data).finish().unwrap();
+        let mut states = vec![map.clone()];
+        for _ in 0..3000 {
+            map.tilt_north().tilt_west().tilt_south().tilt_east();
+            states.push(map.clone());
+        }
+        let (start, end) = (1..states.len())
+            .find_map(|end| {
+                states[..end]
+                    .iter()
+                    .position(|state| *state == states[end])
+                    .map(|start| (start, end))
+            })
+            .unwrap();
+
+        assert_eq!((cycle_start, cycle_len), (start, end - start));
+        assert!((end..states.len()).all(|i| states[i] == states[i - cycle_len]));
+    }
+}
@@ -5,12 +5,10 @@ use nom::{
     character::complete::{
         alpha1,
         char,
-        line_ending,
     },
     combinator::{
         all_consuming,
         map,
-        opt,
     },
     multi::separated_list1,
     sequence::{
@@ -22,10 +20,19 @@ use nom::{
 };
 use std::collections::HashMap;
 
-fn hash(string: &str) -> u32 {
+// Value of the HASH accumulator after each character
+fn hash_steps(string: &str) -> Vec<u32> {
     string
         .chars()
-        .fold(0, |acc, char| ((acc + char as u32) * 17) % 256)
+        .scan(0, |acc, char| {
+            *acc = ((*acc + char as u32) * 17) % 256;
+            Some(*acc)
+        })
+        .collect()
+}
+
+fn hash(string: &str) -> u32 {
+    hash_steps(string).last().copied().unwrap_or_default()
 }
 
 #[derive(Debug)]
@@ -35,7 +42,7 @@ enum Operation<'a> {
 }
 
 impl<'a> Operation<'a> {
-    fn parse(input: &'a str) -> IResult<&str, Self> {
+    fn parse(input: &'a str) -> IResult<&'a str, Self> {
         alt((
             map(terminated(alpha1, char('-')), |label| {
                 Operation::Remove { label }
@@ -53,16 +60,24 @@ impl<'a> Operation<'a> {
     }
 }
 
+// The sequence is a single line, line breaks are only there to wrap it and can be anywhere
+fn unwrap_lines(data: &str) -> String {
+    data.chars().filter(|char| !matches!(char, '\r' | '\n')).collect()
+}
+
+// Comma-separated steps, once the line breaks are removed with `unwrap_lines`
+fn sequence<'a, O>(
+    step: impl FnMut(&'a str) -> IResult<&'a str, O>
+) -> impl FnMut(&'a str) -> IResult<&'a str, Vec<O>> {
+    all_consuming(separated_list1(char(','), step))
+}
+
 fn first(
     name: &str,
     data: &str,
 ) {
-    let (_, sequence) = all_consuming(terminated(
-        separated_list1(char::<&str, nom::error::Error<&str>>(','), is_not(",\n")),
-        opt(line_ending),
-    ))(data)
-    .finish()
-    .unwrap();
+    let data = unwrap_lines(data);
+    let (_, sequence) = sequence(is_not(","))(&data).finish().unwrap();
     let tot: u32 = sequence.iter().map(|part| hash(part)).sum();
     println!("[{}] {:?}", name, tot);
 }
@@ -71,12 +86,8 @@ fn second(
     name: &str,
     data: &str,
 ) {
-    let (_, sequence) = all_consuming(terminated(
-        separated_list1(char::<&str, nom::error::Error<&str>>(','), Operation::parse),
-        opt(line_ending),
-    ))(data)
-    .finish()
-    .unwrap();
+    let data = unwrap_lines(data);
+    let (_, sequence) = sequence(Operation::parse)(&data).finish().unwrap();
 
     let result = sequence.iter().fold(HashMap::new(), |mut map, operation| {
         match operation {
@@ -137,3 +148,32 @@ pub fn run() {
     second("Second example", include_str!("data/day15/ex1")); // 145
     second("Second", include_str!("data/day15/input")); // 265 894
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_steps_of_example() {
+        assert_eq!(hash_steps("HASH"), vec![200, 153, 172, 52]);
+        assert_eq!(hash("HASH"), 52);
+        assert_eq!(hash(""), 0);
+    }
+
+    #[test]
+    fn sequence_over_several_lines() {
+        let single_line = unwrap_lines(include_str!("data/day15/ex1"));
+        let (_, expected) = sequence(is_not(","))(&single_line).finish().unwrap();
+
+        for data in [
+            "rn=1,cm-,qp=3,cm=2,qp-,pc=4,\not=9,ab=5,pc-,pc=6,ot=7\n",
+            "rn=\n1,cm-,qp=3,cm=2,q\r\np-,pc=4,ot=9,ab=5,pc-,pc=6,ot=7\n",
+        ] {
+            let data = unwrap_lines(data);
+            let (_, steps) = sequence(is_not(","))(&data).finish().unwrap();
+            assert_eq!(steps.len(), 11);
+            assert_eq!(steps.iter().map(|step| hash(step)).sum::<u32>(), 1320);
+            assert_eq!(steps, expected);
+        }
+    }
+}
@@ -6,18 +6,16 @@ use nom::{
     },
     combinator::{
         all_consuming,
+        map_res,
         value,
     },
-    multi::many1,
+    multi::many0,
     sequence::terminated,
     Finish,
     IResult,
 };
 use rayon::prelude::*;
-use std::{
-    collections::HashSet,
-    iter::once,
-};
+use std::collections::HashSet;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum Item {
@@ -56,8 +54,8 @@ enum Direction {
 impl Direction {
     fn next(
         &self,
-        coords: (i32, i32),
-    ) -> (i32, i32) {
+        coords: (i64, i64),
+    ) -> (i64, i64) {
         let (x, y) = coords;
         match self {
             Direction::Up => (x, y - 1),
@@ -68,33 +66,106 @@ impl Direction {
     }
 }
 
-fn parse(input: &str) -> IResult<&str, Vec<Vec<Item>>> {
-    all_consuming(many1(terminated(many1(Item::parse), line_ending)))(input)
+// Beam states, as the direction the beam is going to and the tile it is on
+type BeamStates = HashSet<(Direction, (i64, i64))>;
+
+#[derive(Debug)]
+struct Contraption {
+    items: Vec<Vec<Item>>,
 }
 
-fn energize(
-    items: &Vec<Vec<Item>>,
-    first_direction: Direction,
-    first_coords: (i32, i32),
-) -> HashSet<(i32, i32)> {
-    fn run_loop(
-        items: &Vec<Vec<Item>>,
-        active: Vec<Vec<(Direction, (i32, i32))>>,
-        mut done: HashSet<(Direction, (i32, i32))>,
-    ) -> HashSet<(Direction, (i32, i32))> {
-        if active.is_empty() {
-            done
-        } else {
-            let mut next_active = Vec::new();
-            for path in active {
-                if let Some((direction, (x, y))) = path.last() {
-                    let item = items[*y as usize][*x as usize];
+impl Contraption {
+    // An empty contraption is allowed, but every line must have the same, non-zero, width
+    fn new(items: Vec<Vec<Item>>) -> Result<Self, String> {
+        let width = items.first().map(|line| line.len()).unwrap_or_default();
+        for (y, line) in items.iter().enumerate() {
+            if line.is_empty() {
+                return Err(format!("Line {} is empty", y));
+            }
+
+            if line.len() != width {
+                return Err(format!(
+                    "Line {} has {} items, expected {}",
+                    y,
+                    line.len(),
+                    width
+                ));
+            }
+        }
+
+        Ok(Contraption { items })
+    }
+
+    fn parse(input: &str) -> IResult<&str, Self> {
+        all_consuming(map_res(
+            many0(terminated(many0(Item::parse), line_ending)),
+            Contraption::new,
+        ))(input)
+    }
+
+    fn width(&self) -> usize {
+        self.items
+            .first()
+            .map(|line| line.len())
+            .unwrap_or_default()
+    }
+
+    fn height(&self) -> usize {
+        self.items.len()
+    }
+
+    fn contains(
+        &self,
+        coords: (i64, i64),
+    ) -> bool {
+        let (x, y) = coords;
+        0 <= x && x < self.width() as i64 && 0 <= y && y < self.height() as i64
+    }
+
+    fn item_at(
+        &self,
+        coords: (i64, i64),
+    ) -> Item {
+        let (x, y) = coords;
+        self.items[y as usize][x as usize]
+    }
+
+    // Follows the beams, returning every beam state reached along with the peak number of active
+    // beams and the total number of beam steps processed
+    fn run_beams(
+        &self,
+        first_direction: Direction,
+        first_coords: (i64, i64),
+    ) -> (BeamStates, usize, usize) {
+        // Nothing to energize from outside, which is always the case in an empty contraption
+        if !self.contains(first_coords) {
+            return (HashSet::new(), 0, 0);
+        }
+
+        // Every beam state is recorded in `visited` as soon as it is reached,
+        // so a state is never expanded twice, even when beams loop
+        fn run_loop(
+            contraption: &Contraption,
+            active: Vec<(Direction, (i64, i64))>,
+            mut visited: BeamStates,
+            peak_frontier: usize,
+            total_steps: usize,
+        ) -> (BeamStates, usize, usize) {
+            if active.is_empty() {
+                (visited, peak_frontier, total_steps)
+            } else {
+                let peak_frontier = peak_frontier.max(active.len());
+                let total_steps = total_steps + active.len();
+
+                let mut next_active = Vec::new();
+                for (direction, coords) in active {
+                    let item = contraption.item_at(coords);
                     let next_directions = match item {
-                        Item::Empty => vec![*direction],
+                        Item::Empty => vec![direction],
 
                         Item::VerticalSplitter => {
                             match direction {
-                                Direction::Up | Direction::Down => vec![*direction],
+                                Direction::Up | Direction::Down => vec![direction],
                                 Direction::Left | Direction::Right => {
                                     vec![Direction::Up, Direction::Down]
                                 }
@@ -103,7 +174,7 @@ fn energize(
 
                         Item::HorizontalSplitter => {
                             match direction {
-                                Direction::Left | Direction::Right => vec![*direction],
+                                Direction::Left | Direction::Right => vec![direction],
                                 Direction::Up | Direction::Down => {
                                     vec![Direction::Left, Direction::Right]
                                 }
@@ -130,89 +201,271 @@ fn energize(
                     };
 
                     for direction in next_directions {
-                        let (x, y) = direction.next((*x, *y));
-                        let already_visited = done.contains(&(direction, (x, y)));
-                        let next_in_grid = 0 <= x
-                            && x < items.first().map(|line| line.len()).unwrap_or_default() as i32
-                            && 0 <= y
-                            && y < items.len() as i32;
-
-                        if !already_visited && next_in_grid {
-                            next_active.push(
-                                path.iter()
-                                    .copied()
-                                    .chain(once((direction, (x, y))))
-                                    .collect(),
-                            );
-                        } else {
-                            path.iter().for_each(|x| {
-                                done.insert(*x);
-                            });
+                        let next = direction.next(coords);
+                        if contraption.contains(next) && visited.insert((direction, next)) {
+                            next_active.push((direction, next));
                         }
                     }
                 }
-            }
 
-            run_loop(items, next_active, done)
+                run_loop(
+                    contraption,
+                    next_active,
+                    visited,
+                    peak_frontier,
+                    total_steps,
+                )
+            }
         }
+
+        let first = (first_direction, first_coords);
+        run_loop(self, vec![first], HashSet::from([first]), 0, 0)
     }
 
-    let energized = run_loop(
-        items,
-        vec![vec![(first_direction, first_coords)]],
-        HashSet::new(),
-    );
+    fn energized_tiles(
+        &self,
+        first_direction: Direction,
+        first_coords: (i64, i64),
+    ) -> HashSet<(i64, i64)> {
+        let (visited, _, _) = self.run_beams(first_direction, first_coords);
 
-    energized
-        .iter()
-        .map(|(_, coords)| coords)
-        .copied()
-        .collect()
+        visited.iter().map(|(_, coords)| coords).copied().collect()
+    }
+
+    fn energize(
+        &self,
+        first_direction: Direction,
+        first_coords: (i64, i64),
+    ) -> usize {
+        self.energized_tiles(first_direction, first_coords).len()
+    }
+
+    // Energized tiles in row-major order, for stable comparisons
+    #[cfg(test)]
+    fn energized_sorted(
+        &self,
+        first_direction: Direction,
+        first_coords: (i64, i64),
+    ) -> Vec<(i64, i64)> {
+        let mut energized: Vec<_> = self
+            .energized_tiles(first_direction, first_coords)
+            .into_iter()
+            .collect();
+        energized.sort_by_key(|(x, y)| (*y, *x));
+        energized
+    }
+
+    // Returns the energized tiles, the peak number of active beams and the total beam steps
+    fn energize_with_stats(
+        &self,
+        first_direction: Direction,
+        first_coords: (i64, i64),
+    ) -> (usize, usize, usize) {
+        let (visited, peak_frontier, total_steps) = self.run_beams(first_direction, first_coords);
+        let energized = visited
+            .iter()
+            .map(|(_, coords)| coords)
+            .collect::<HashSet<_>>()
+            .len();
+
+        (energized, peak_frontier, total_steps)
+    }
+
+    // Same as `energize`, the beam may start anywhere as long as it is inside the contraption
+    fn energize_from(
+        &self,
+        first_direction: Direction,
+        first_coords: (i64, i64),
+    ) -> Result<usize, String> {
+        if self.contains(first_coords) {
+            Ok(self.energize(first_direction, first_coords))
+        } else {
+            Err(format!(
+                "Start {:?} is outside of the {}x{} contraption",
+                first_coords,
+                self.width(),
+                self.height()
+            ))
+        }
+    }
 }
 
 fn first(
     name: &str,
     data: &str,
 ) {
-    let (_, items) = parse(data).finish().unwrap();
+    let (_, contraption) = Contraption::parse(data).finish().unwrap();
 
-    let energized = energize(&items, Direction::Right, (0, 0));
-    println!("[{}] Energized tiles {:?}", name, energized.len());
+    let energized = contraption.energize_from(Direction::Right, (0, 0)).unwrap();
+    println!("[{}] Energized tiles {:?}", name, energized);
 }
 
 fn second(
     name: &str,
     data: &str,
 ) {
-    let (_, items) = parse(data).finish().unwrap();
+    let (_, contraption) = Contraption::parse(data).finish().unwrap();
+    let width = contraption.width();
+    let height = contraption.height();
 
     // First column, x=0, moving y, going right
-    let max_energized = (0..(items.len() - 1))
+    let max_energized = (0..height.saturating_sub(1))
         .map(|y| (Direction::Right, (0, y)))
         .chain(
             // Last column, x=len-1, moving y, going left
-            (0..(items.len() - 1)).map(|y| (Direction::Left, (items[0].len() - 1, y))),
+            (0..height.saturating_sub(1)).map(|y| (Direction::Left, (width - 1, y))),
         )
         .chain(
             // First line, moving x, y=0, going down
-            (0..(items[0].len() - 1)).map(|x| (Direction::Down, (x, 0))),
+            (0..width.saturating_sub(1)).map(|x| (Direction::Down, (x, 0))),
         )
         .chain(
             // Last line, moving x, y=len-1, going up
-            (0..(items[0].len() - 1)).map(|x| (Direction::Up, (x, items.len() - 1))),
+            (0..width.saturating_sub(1)).map(|x| (Direction::Up, (x, height - 1))),
         )
         .collect::<Vec<_>>()
         .par_iter()
-        .map(|(direction, (x, y))| energize(&items, *direction, (*x as i32, *y as i32)).len())
+        .map(|(direction, (x, y))| contraption.energize(*direction, (*x as i64, *y as i64)))
         .max()
         .unwrap_or_default();
 
     println!("[{}] Max energized tiles {:?}", name, max_energized);
 }
 
+fn first_with_stats(
+    name: &str,
+    data: &str,
+) {
+    let (_, contraption) = Contraption::parse(data).finish().unwrap();
+
+    let (energized, peak_frontier, total_steps) =
+        contraption.energize_with_stats(Direction::Right, (0, 0));
+    println!(
+        "[{}] Energized tiles {:?}, peak frontier {}, total steps {}",
+        name, energized, peak_frontier, total_steps
+    );
+}
+
 pub fn run() {
     first("First example", include_str!("data/day16/ex1")); // 46
     first("First", include_str!("data/day16/input")); // 7472
     second("Second example", include_str!("data/day16/ex1")); // 46
     second("Second", include_str!("data/day16/input")); // 46
+    first_with_stats("First example with stats", include_str!("data/day16/ex1"));
+    first_with_stats("First with stats", include_str!("data/day16/input"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn energize_terminates_on_loop() {
+        // The mirrors send the beam around the border forever
+        let (_, contraption) = Contraption::parse("/..\\\n....\n\\../\n").finish().unwrap();
+
+        let energized = contraption.energized_tiles(Direction::Right, (1, 0));
+        assert_eq!(energized.len(), 10);
+        assert!(!energized.contains(&(1, 1)));
+        assert!(!energized.contains(&(2, 1)));
+    }
+
+    #[test]
+    fn stats_of_split_and_looping_beams() {
+        // Split up (leaving) and down, then left and right at the bottom, both leaving
+        let (_, contraption) = Contraption::parse(".|.\n...\n.-.\n").finish().unwrap();
+        assert_eq!(
+            contraption.energize_with_stats(Direction::Right, (0, 0)),
+            (6, 2, 6)
+        );
+
+        // A single beam going around the border once before reaching a known state
+        let (_, contraption) = Contraption::parse("/..\\\n....\n\\../\n").finish().unwrap();
+        assert_eq!(
+            contraption.energize_with_stats(Direction::Right, (1, 0)),
+            (10, 1, 10)
+        );
+    }
+
+    #[test]
+    fn energized_tiles_of_example() {
+        let (_, contraption) = Contraption::parse(include_str!("data/day16/ex1"))
+            .finish()
+            .unwrap();
+
+        // As drawn in the puzzle
+        let expected: Vec<_> = [
+            "######....",
+            ".#...#....",
+            ".#...#####",
+            ".#...##...",
+            ".#...##...",
+            ".#...##...",
+            ".#..####..",
+            "########..",
+            ".#######..",
+            ".#...#.#..",
+        ]
+        .iter()
+        .enumerate()
+        .flat_map(|(y, line)| {
+            line.chars()
+                .enumerate()
+                .filter(|(_, c)| *c == '#')
+                .map(move |(x, _)| (x as i64, y as i64))
+        })
+        .collect();
+
+        assert_eq!(
+            contraption.energized_sorted(Direction::Right, (0, 0)),
+            expected
+        );
+    }
+
+    #[test]
+    fn energize_from_interior_tile() {
+        let (_, contraption) = Contraption::parse(include_str!("data/day16/ex1"))
+            .finish()
+            .unwrap();
+
+        // Right along the middle line, then down the last column until the bottom mirror
+        assert_eq!(contraption.energize_from(Direction::Right, (5, 5)), Ok(8));
+        assert!(contraption
+            .energize_from(Direction::Right, (10, 5))
+            .is_err());
+        assert!(contraption.energize_from(Direction::Up, (5, -1)).is_err());
+    }
+
+    #[test]
+    fn far_away_start_is_rejected() {
+        let (_, contraption) = Contraption::parse(include_str!("data/day16/ex1"))
+            .finish()
+            .unwrap();
+
+        assert!(contraption
+            .energize_from(Direction::Right, (i64::MAX, 0))
+            .is_err());
+        assert_eq!(
+            contraption.energize(Direction::Left, (i64::MIN, i64::MIN)),
+            0
+        );
+        assert_eq!(contraption.energize(Direction::Right, (0, 0)), 46);
+    }
+
+    #[test]
+    fn empty_contraption() {
+        let (_, contraption) = Contraption::parse("").finish().unwrap();
+
+        assert_eq!((contraption.width(), contraption.height()), (0, 0));
+        assert_eq!(contraption.energize(Direction::Right, (0, 0)), 0);
+        assert!(contraption.energize_from(Direction::Right, (0, 0)).is_err());
+    }
+
+    #[test]
+    fn empty_or_ragged_lines_are_rejected() {
+        assert!(Contraption::parse("\n").is_err());
+        assert!(Contraption::parse("..\n\n..\n").is_err());
+        assert!(Contraption::parse("..\n.\n").is_err());
+        assert!(Contraption::parse("..\n..\n").is_ok());
+    }
 }
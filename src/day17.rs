@@ -6,6 +6,7 @@ use nom::{
     combinator::{
         all_consuming,
         map,
+        map_res,
     },
     multi::many1,
     sequence::terminated,
@@ -18,21 +19,60 @@ use std::{
         BinaryHeap,
         HashMap,
     },
+    fmt::{
+        Display,
+        Formatter,
+    },
 };
 
 #[derive(Debug)]
 struct Grid {
     points: Vec<Vec<u8>>,
+    // Computed once, as they are checked for every neighbour during the search
+    width: i32,
+    height: i32,
 }
 
 impl Grid {
+    fn new(points: Vec<Vec<u8>>) -> Self {
+        Grid {
+            width: points.first().map(|line| line.len()).unwrap_or_default() as i32,
+            height: points.len() as i32,
+            points,
+        }
+    }
+
+    // Heat losses are single digits, and every row must have the same width
+    fn from_rows(rows: Vec<Vec<u8>>) -> Result<Self, String> {
+        let width = rows.first().map(|row| row.len()).unwrap_or_default();
+        for (y, row) in rows.iter().enumerate() {
+            if row.len() != width {
+                return Err(format!(
+                    "Row {} has {} blocks, expected {}",
+                    y,
+                    row.len(),
+                    width
+                ));
+            }
+
+            if let Some(x) = row.iter().position(|heat_loss| *heat_loss > 9) {
+                return Err(format!(
+                    "Heat loss {} at ({}, {}) is not a digit",
+                    row[x], x, y
+                ));
+            }
+        }
+
+        Ok(Self::new(rows))
+    }
+
     fn parse(input: &str) -> IResult<&str, Self> {
-        all_consuming(map(
+        all_consuming(map_res(
             many1(terminated(
-                many1(map(satisfy(|c| c.is_numeric()), |c| c as u8 - b'0')),
+                many1(map(satisfy(|c| c.is_ascii_digit()), |c| c as u8 - b'0')),
                 line_ending,
             )),
-            |points| Self { points },
+            Self::from_rows,
         ))(input)
     }
 
@@ -42,12 +82,28 @@ impl Grid {
         end: (i32, i32),
         min_step: u8,
         max_step: u8,
+        movement: Movement,
     ) -> Option<u32> {
+        self.shortest_path_with_trace(start, end, min_step, max_step, movement)
+            .map(|(cost, _)| cost)
+    }
+
+    // Same as `shortest_path`, also returning the blocks of the path, from start to end
+    fn shortest_path_with_trace(
+        &self,
+        start: (i32, i32),
+        end: (i32, i32),
+        min_step: u8,
+        max_step: u8,
+        movement: Movement,
+    ) -> Option<(u32, Vec<(i32, i32)>)> {
         let mut distances = HashMap::<Key, u32>::new();
+        // Where the best known path to each state comes from
+        let mut previous = HashMap::<Key, Key>::new();
         let mut heap = BinaryHeap::new();
 
         // Initialize
-        for direction in [Direction::Down, Direction::Right] {
+        for &direction in movement.directions() {
             let state = State {
                 cost: 0,
                 coords: start,
@@ -70,7 +126,15 @@ impl Grid {
         {
             // We reached the final point
             if coords == end && steps >= min_step {
-                return Some(cost);
+                let mut path = vec![coords];
+                let mut key = Key::from(state);
+                while let Some(&from) = previous.get(&key) {
+                    path.push(from.coords);
+                    key = from;
+                }
+                path.reverse();
+
+                return Some((cost, path));
             }
 
             // Otherwise check if we got a better distance
@@ -81,7 +145,7 @@ impl Grid {
                 continue;
             }
 
-            for (next_direction, (x, y)) in self.adjacent(coords, direction) {
+            for (next_direction, (x, y)) in self.adjacent(coords, direction, movement) {
                 let next = State {
                     cost: cost + self.points[y as usize][x as usize] as u32,
                     coords: (x, y),
@@ -109,6 +173,7 @@ impl Grid {
                 // We continue checking paths and register the distances
                 heap.push(next);
                 distances.insert(next.into(), next.cost);
+                previous.insert(next.into(), state.into());
             }
         }
 
@@ -120,43 +185,69 @@ impl Grid {
         coords: (i32, i32),
     ) -> bool {
         let (x, y) = coords;
-        0 <= x
-            && x < self
-                .points
-                .first()
-                .map(|line| line.len())
-                .unwrap_or_default() as i32
-            && 0 <= y
-            && y < self.points.len() as i32
+        0 <= x && x < self.width && 0 <= y && y < self.height
     }
 
     fn adjacent(
         &self,
         coords: (i32, i32),
         coming_from: Direction,
+        movement: Movement,
     ) -> Vec<(Direction, (i32, i32))> {
-        [
-            Direction::Up,
-            Direction::Down,
-            Direction::Left,
-            Direction::Right,
-        ]
-        .iter()
-        .filter_map(|direction| {
-            // Don't want to go back
-            if *direction == coming_from.opposite() {
-                None
-            } else {
-                let next = direction.next(coords);
-                // Filter out points outside of the grid
-                if self.contains(next) {
-                    Some((*direction, next))
-                } else {
+        movement
+            .directions()
+            .iter()
+            .filter_map(|direction| {
+                // Don't want to go back
+                if *direction == coming_from.opposite() {
                     None
+                } else {
+                    let next = direction.next(coords);
+                    // Filter out points outside of the grid
+                    if self.contains(next) {
+                        Some((*direction, next))
+                    } else {
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    // Shows the heat losses, replacing each block of the path after the start with the direction
+    // the crucible moved in to reach it
+    fn render_path(
+        &self,
+        path: &[(i32, i32)],
+    ) -> String {
+        let arrows = path
+            .windows(2)
+            .filter_map(|pair| {
+                Direction::between(pair[0], pair[1]).map(|direction| (pair[1], direction.arrow()))
+            })
+            .collect::<HashMap<_, _>>();
+
+        let mut rendered = String::new();
+        for (y, line) in self.points.iter().enumerate() {
+            for (x, heat_loss) in line.iter().enumerate() {
+                match arrows.get(&(x as i32, y as i32)) {
+                    Some(arrow) => rendered.push(*arrow),
+                    None => rendered.push((b'0' + heat_loss) as char),
                 }
             }
-        })
-        .collect()
+            rendered.push('\n');
+        }
+
+        rendered
+    }
+}
+
+impl Display for Grid {
+    fn fmt(
+        &self,
+        f: &mut Formatter<'_>,
+    ) -> std::fmt::Result {
+        f.write_str(&self.render_path(&[]))
     }
 }
 
@@ -166,6 +257,10 @@ enum Direction {
     Down,
     Left,
     Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
 }
 
 impl Direction {
@@ -175,6 +270,10 @@ impl Direction {
             Direction::Down => Direction::Up,
             Direction::Left => Direction::Right,
             Direction::Right => Direction::Left,
+            Direction::UpLeft => Direction::DownRight,
+            Direction::UpRight => Direction::DownLeft,
+            Direction::DownLeft => Direction::UpRight,
+            Direction::DownRight => Direction::UpLeft,
         }
     }
 
@@ -188,6 +287,68 @@ impl Direction {
             Direction::Down => (x, y + 1),
             Direction::Left => (x - 1, y),
             Direction::Right => (x + 1, y),
+            Direction::UpLeft => (x - 1, y - 1),
+            Direction::UpRight => (x + 1, y - 1),
+            Direction::DownLeft => (x - 1, y + 1),
+            Direction::DownRight => (x + 1, y + 1),
+        }
+    }
+
+    // The direction leading from a block to an adjacent one
+    fn between(
+        from: (i32, i32),
+        to: (i32, i32),
+    ) -> Option<Direction> {
+        Movement::King
+            .directions()
+            .iter()
+            .find(|direction| direction.next(from) == to)
+            .copied()
+    }
+
+    fn arrow(&self) -> char {
+        match self {
+            Direction::Up => '^',
+            Direction::Down => 'v',
+            Direction::Left => '<',
+            Direction::Right => '>',
+            Direction::UpLeft | Direction::DownRight => '\\',
+            Direction::UpRight | Direction::DownLeft => '/',
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Movement {
+    // Up, down, left and right only
+    Orthogonal,
+    // Orthogonal and diagonal moves, like a chess king
+    King,
+}
+
+impl Movement {
+    fn directions(&self) -> &'static [Direction] {
+        match self {
+            Movement::Orthogonal => {
+                &[
+                    Direction::Up,
+                    Direction::Down,
+                    Direction::Left,
+                    Direction::Right,
+                ]
+            }
+            Movement::King => {
+                &[
+                    Direction::Up,
+                    Direction::Down,
+                    Direction::Left,
+                    Direction::Right,
+                    Direction::UpLeft,
+                    Direction::UpRight,
+                    Direction::DownLeft,
+                    Direction::DownRight,
+                ]
+            }
         }
     }
 }
@@ -242,16 +403,35 @@ fn first(
     let (_, grid) = Grid::parse(data).finish().unwrap();
     let result = grid.shortest_path(
         (0, 0),
-        (
-            grid.points[0].len() as i32 - 1,
-            grid.points.len() as i32 - 1,
-        ),
+        (grid.width - 1, grid.height - 1),
         1,
         3,
+        Movement::Orthogonal,
     );
     println!("[{}] Shortest path: {:?}", name, result);
 }
 
+fn trace(
+    name: &str,
+    data: &str,
+) {
+    let (_, grid) = Grid::parse(data).finish().unwrap();
+    if let Some((cost, path)) = grid.shortest_path_with_trace(
+        (0, 0),
+        (grid.width - 1, grid.height - 1),
+        1,
+        3,
+        Movement::Orthogonal,
+    ) {
+        println!(
+            "[{}] Shortest path: {}\n{}",
+            name,
+            cost,
+            grid.render_path(&path)
+        );
+    }
+}
+
 fn second(
     name: &str,
     data: &str,
@@ -259,12 +439,25 @@ fn second(
     let (_, grid) = Grid::parse(data).finish().unwrap();
     let result = grid.shortest_path(
         (0, 0),
-        (
-            grid.points[0].len() as i32 - 1,
-            grid.points.len() as i32 - 1,
-        ),
+        (grid.width - 1, grid.height - 1),
         4,
         10,
+        Movement::Orthogonal,
+    );
+    println!("[{}] Shortest path: {:?}", name, result);
+}
+
+fn king_moves(
+    name: &str,
+    data: &str,
+) {
+    let (_, grid) = Grid::parse(data).finish().unwrap();
+    let result = grid.shortest_path(
+        (0, 0),
+        (grid.width - 1, grid.height - 1),
+        1,
+        3,
+        Movement::King,
     );
     println!("[{}] Shortest path: {:?}", name, result);
 }
@@ -272,6 +465,124 @@ fn second(
 pub fn run() {
     first("First example", include_str!("data/day17/ex1")); // 102
     first("First", include_str!("data/day17/input")); // 1263
+    trace("Trace example", include_str!("data/day17/ex1")); // 102
     second("Second example", include_str!("data/day17/ex1")); // 94
     second("Second", include_str!("data/day17/input")); // 94
+    king_moves("King moves example", include_str!("data/day17/ex1")); // 65
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unreachable_target() {
+        let (_, grid) = Grid::parse("123\n456\n789\n").finish().unwrap();
+
+        // The end cannot be reached after moving at least 4 blocks in a straight line
+        let result = grid.shortest_path((0, 0), (2, 2), 4, 10, Movement::Orthogonal);
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn shortest_path_from_rows() {
+        let grid = Grid::from_rows(vec![vec![1, 1, 9], vec![9, 1, 9], vec![9, 1, 1]]).unwrap();
+
+        // Down the middle column, then right
+        assert_eq!(
+            grid.shortest_path((0, 0), (2, 2), 1, 3, Movement::Orthogonal),
+            Some(4)
+        );
+        // Turning at every block goes through a 9
+        assert_eq!(
+            grid.shortest_path((0, 0), (2, 2), 1, 1, Movement::Orthogonal),
+            Some(12)
+        );
+    }
+
+    #[test]
+    fn king_moves_take_diagonals() {
+        let grid = Grid::from_rows(vec![vec![1, 9, 9], vec![9, 1, 9], vec![9, 9, 1]]).unwrap();
+
+        // Straight down the diagonal, instead of going through two 9s
+        assert_eq!(
+            grid.shortest_path((0, 0), (2, 2), 1, 3, Movement::King),
+            Some(2)
+        );
+        assert_eq!(
+            grid.shortest_path((0, 0), (2, 2), 1, 3, Movement::Orthogonal),
+            Some(20)
+        );
+
+        let (_, grid) = Grid::parse(include_str!("data/day17/ex1"))
+            .finish()
+            .unwrap();
+        assert_eq!(
+            grid.shortest_path((0, 0), (12, 12), 1, 3, Movement::King),
+            Some(65)
+        );
+    }
+
+    #[test]
+    fn invalid_rows_are_rejected() {
+        assert_eq!(
+            Grid::from_rows(vec![vec![1, 2], vec![3]]).err(),
+            Some(String::from("Row 1 has 1 blocks, expected 2"))
+        );
+        assert_eq!(
+            Grid::from_rows(vec![vec![1, 2], vec![3, 10]]).err(),
+            Some(String::from("Heat loss 10 at (1, 1) is not a digit"))
+        );
+        assert!(Grid::parse("12\n3\n").is_err());
+    }
+
+    #[test]
+    fn streak_constraints_on_example() {
+        let (_, grid) = Grid::parse(include_str!("data/day17/ex1"))
+            .finish()
+            .unwrap();
+
+        // (min_step, max_step, heat loss), checked against an independent search
+        let cases = [
+            (1, 3, 102),
+            (4, 10, 94),
+            (4, 4, 130),
+            (1, 1, 133),
+            (1, 10, 82),
+        ];
+        for (min_step, max_step, heat_loss) in cases {
+            assert_eq!(
+                grid.shortest_path((0, 0), (12, 12), min_step, max_step, Movement::Orthogonal),
+                Some(heat_loss),
+                "min_step {} max_step {}",
+                min_step,
+                max_step
+            );
+        }
+    }
+
+    #[test]
+    fn render_traced_path() {
+        let grid = Grid::from_rows(vec![vec![1, 1, 9], vec![9, 1, 9], vec![9, 1, 1]]).unwrap();
+        assert_eq!(grid.to_string(), "119\n919\n911\n");
+
+        let (cost, path) = grid
+            .shortest_path_with_trace((0, 0), (2, 2), 1, 3, Movement::Orthogonal)
+            .unwrap();
+        assert_eq!(path, vec![(0, 0), (1, 0), (1, 1), (1, 2), (2, 2)]);
+        assert_eq!(grid.render_path(&path), "1>9\n9v9\n9v>\n");
+
+        // The heat loss of the path is the one of every block but the start
+        let heat_loss: u32 = path[1..]
+            .iter()
+            .map(|&(x, y)| grid.points[y as usize][x as usize] as u32)
+            .sum();
+        assert_eq!(heat_loss, cost);
+
+        // Diagonal moves
+        assert_eq!(
+            grid.render_path(&[(0, 0), (1, 1), (0, 2)]),
+            "119\n9\\9\n/11\n"
+        );
+    }
 }
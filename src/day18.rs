@@ -1,12 +1,12 @@
 use nom::{
     branch::alt,
     bytes::complete::{
-        is_not,
         tag,
         take,
     },
     character,
     character::complete::{
+        anychar,
         char,
         line_ending,
         space1,
@@ -45,13 +45,14 @@ impl Direction {
         ))(input)
     }
 
-    fn parse_2(input: &str) -> IResult<&str, Self> {
-        alt((
-            value(Direction::Up, char('3')),
-            value(Direction::Down, char('1')),
-            value(Direction::Left, char('2')),
-            value(Direction::Right, char('0')),
-        ))(input)
+    fn from_hex(nibble: char) -> Result<Self, String> {
+        match nibble {
+            '3' => Ok(Direction::Up),
+            '1' => Ok(Direction::Down),
+            '2' => Ok(Direction::Left),
+            '0' => Ok(Direction::Right),
+            _ => Err(format!("Invalid direction nibble '{}'", nibble)),
+        }
     }
 }
 
@@ -61,43 +62,86 @@ struct Drill {
     length: i64,
 }
 
-impl Drill {
-    fn parse_1(input: &str) -> IResult<&str, Self> {
+// A line of the dig plan, read for both parts
+#[derive(Debug)]
+struct RawDrill {
+    direction: Direction,
+    length: i64,
+    // Length and direction nibble hidden in the color, for the second part
+    // The nibble is decoded later, so an invalid one can be reported with its line
+    hex_length: i64,
+    hex_direction: char,
+}
+
+impl RawDrill {
+    fn parse(input: &str) -> IResult<&str, Self> {
         map(
             tuple((
                 Direction::parse_1,
                 space1,
                 character::complete::i64,
-                is_not("\n"),
-            )),
-            |(direction, _, length, _)| Self { direction, length },
-        )(input)
-    }
-
-    fn parse_2(input: &str) -> IResult<&str, Self> {
-        map(
-            tuple((
-                is_not("("),
+                space1,
                 delimited(
                     tag("(#"),
-                    tuple((
-                        map_res(take(5u8), |x| i64::from_str_radix(x, 16)),
-                        Direction::parse_2,
-                    )),
+                    tuple((map_res(take(5u8), |x| i64::from_str_radix(x, 16)), anychar)),
                     tag(")"),
                 ),
             )),
-            |(_, (length, direction))| Self { direction, length },
+            |(direction, _, length, _, (hex_length, hex_direction))| {
+                RawDrill {
+                    direction,
+                    length,
+                    hex_length,
+                    hex_direction,
+                }
+            },
         )(input)
     }
 }
 
+fn parse_raw(input: &str) -> IResult<&str, Vec<RawDrill>> {
+    all_consuming(many1(terminated(RawDrill::parse, line_ending)))(input)
+}
+
 fn parse_1(input: &str) -> IResult<&str, Vec<Drill>> {
-    all_consuming(many1(terminated(Drill::parse_1, line_ending)))(input)
+    map(parse_raw, |raw_drills| decode_1(&raw_drills))(input)
 }
 
-fn parse_2(input: &str) -> IResult<&str, Vec<Drill>> {
-    all_consuming(many1(terminated(Drill::parse_2, line_ending)))(input)
+fn parse_2(input: &str) -> Result<Vec<Drill>, String> {
+    let (_, raw_drills) = parse_raw(input)
+        .finish()
+        .map_err(|error| format!("{:?}", error))?;
+
+    decode_2(&raw_drills)
+}
+
+fn decode_1(raw_drills: &[RawDrill]) -> Vec<Drill> {
+    raw_drills
+        .iter()
+        .map(|raw_drill| {
+            Drill {
+                direction: raw_drill.direction,
+                length: raw_drill.length,
+            }
+        })
+        .collect()
+}
+
+fn decode_2(raw_drills: &[RawDrill]) -> Result<Vec<Drill>, String> {
+    raw_drills
+        .iter()
+        .enumerate()
+        .map(|(index, raw_drill)| {
+            Direction::from_hex(raw_drill.hex_direction)
+                .map(|direction| {
+                    Drill {
+                        direction,
+                        length: raw_drill.hex_length,
+                    }
+                })
+                .map_err(|error| format!("{} on line {}", error, index + 1))
+        })
+        .collect()
 }
 
 fn shoelace(points: &Vec<(i64, i64)>) -> i64 {
@@ -126,7 +170,53 @@ fn perimeter(points: &Vec<(i64, i64)>) -> i64 {
         .sum::<i64>()
 }
 
-fn process(drills: Vec<Drill>) -> i64 {
+// Pick's theorem and the shoelace formula only hold for a simple polygon: the path must come back
+// to its start, and two segments may only meet at the corner they share
+fn check_simple(points: &[(i64, i64)]) -> Result<(), String> {
+    if points.first() != points.last() {
+        return Err(format!(
+            "The dig plan ends at {:?} instead of its start",
+            points.last()
+        ));
+    }
+
+    let segments: Vec<_> = points
+        .windows(2)
+        .map(|window| (window[0], window[1]))
+        .collect();
+    let bounds = |((x1, y1), (x2, y2)): ((i64, i64), (i64, i64))| {
+        (x1.min(x2), x1.max(x2), y1.min(y2), y1.max(y2))
+    };
+
+    for (i, first) in segments.iter().enumerate() {
+        for (j, second) in segments.iter().enumerate().skip(i + 1) {
+            let (min_x1, max_x1, min_y1, max_y1) = bounds(*first);
+            let (min_x2, max_x2, min_y2, max_y2) = bounds(*second);
+
+            // Segments are either horizontal or vertical, so they meet where their bounds overlap
+            let overlap_x = (min_x1.max(min_x2), max_x1.min(max_x2));
+            let overlap_y = (min_y1.max(min_y2), max_y1.min(max_y2));
+            if overlap_x.0 > overlap_x.1 || overlap_y.0 > overlap_y.1 {
+                continue;
+            }
+
+            // Consecutive segments, including the last and the first one, share a corner
+            let consecutive = j == i + 1 || (i == 0 && j == segments.len() - 1);
+            let single_corner = overlap_x.0 == overlap_x.1 && overlap_y.0 == overlap_y.1;
+            if !(consecutive && single_corner) {
+                return Err(format!(
+                    "The dig plan crosses itself around ({}, {})",
+                    overlap_x.0, overlap_y.0
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Returns the shoelace area, the perimeter and the total area of the trench
+fn metrics(drills: &[Drill]) -> Result<(i64, i64, i64), String> {
     let mut current = (0i64, 0i64);
     let mut points = vec![current];
     for Drill {
@@ -134,6 +224,7 @@ fn process(drills: Vec<Drill>) -> i64 {
     } in drills
     {
         let (x, y) = current;
+        let length = *length;
         current = match direction {
             Direction::Up => (x, y - length),
             Direction::Down => (x, y + length),
@@ -144,9 +235,29 @@ fn process(drills: Vec<Drill>) -> i64 {
         points.push(current);
     }
 
+    check_simple(&points)?;
+
     let shoelace_area = shoelace(&points);
     let perimeter_area = perimeter(&points);
-    shoelace_area + perimeter_area / 2 + 1
+    Ok((
+        shoelace_area,
+        perimeter_area,
+        shoelace_area + perimeter_area / 2 + 1,
+    ))
+}
+
+// Volume of lava held by the trench when dug `depth` meters deep
+fn volume(
+    drills: &[Drill],
+    depth: i64,
+) -> Result<i64, String> {
+    let (_, _, total) = metrics(drills)?;
+    Ok(total * depth)
+}
+
+fn process(drills: Vec<Drill>) -> Result<i64, String> {
+    // With a depth of one meter, the volume is the area
+    volume(&drills, 1)
 }
 
 fn first(
@@ -154,7 +265,7 @@ fn first(
     data: &str,
 ) {
     let (_, drills) = parse_1(data).finish().unwrap();
-    let area = process(drills);
+    let area = process(drills).unwrap();
     println!("[{}] Area is {:#?}", name, area);
 }
 
@@ -162,14 +273,102 @@ fn second(
     name: &str,
     data: &str,
 ) {
-    let (_, drills) = parse_2(data).finish().unwrap();
-    let area = process(drills);
+    let drills = parse_2(data).unwrap();
+    let area = process(drills).unwrap();
     println!("[{}] Area is {:#?}", name, area);
 }
 
+// Areas of both parts, from a single parsing of the dig plan
+fn solve(data: &str) -> Result<(i64, i64), String> {
+    let (_, raw_drills) = parse_raw(data)
+        .finish()
+        .map_err(|error| format!("{:?}", error))?;
+
+    Ok((
+        process(decode_1(&raw_drills))?,
+        process(decode_2(&raw_drills)?)?,
+    ))
+}
+
+fn both(
+    name: &str,
+    data: &str,
+) {
+    let (first_area, second_area) = solve(data).unwrap();
+    println!("[{}] Areas are {} and {}", name, first_area, second_area);
+}
+
 pub fn run() {
     first("First example", include_str!("data/day18/ex1")); // 62
     first("First", include_str!("data/day18/input")); // 50603
     second("Second example", include_str!("data/day18/ex1")); // 952 408 144 115
     second("Second", include_str!("data/day18/input")); // 96 556 251 590 677
+    both("Both", include_str!("data/day18/input")); // 50603 and 96556251590677
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(data: &str) -> Result<i64, String> {
+        let (_, drills) = parse_1(data).finish().unwrap();
+        process(drills)
+    }
+
+    #[test]
+    fn l_shaped_trench() {
+        let data = "R 2 (#000000)\nD 1 (#000000)\nL 1 (#000000)\nD 1 (#000000)\nL 1 (#000000)\nU 2 (#000000)\n";
+        assert_eq!(area(data), Ok(8));
+
+        // The path through the centers of the dug cubes encloses 3 square meters and is 8 meters
+        // long. Half a cube lies outside the path along it, and the corners add up to one more
+        let (_, drills) = parse_1(data).finish().unwrap();
+        assert_eq!(metrics(&drills), Ok((3, 8, 3 + 8 / 2 + 1)));
+    }
+
+    #[test]
+    fn volume_grows_with_depth() {
+        let (_, drills) = parse_1(include_str!("data/day18/ex1")).finish().unwrap();
+
+        for depth in [1, 2, 5] {
+            assert_eq!(volume(&drills, depth), Ok(62 * depth));
+        }
+    }
+
+    #[test]
+    fn self_touching_trench_is_rejected() {
+        // Two squares touching by a corner, like a figure eight
+        let data = "R 1 (#000000)\nD 1 (#000000)\nR 1 (#000000)\nD 1 (#000000)\nL 1 (#000000)\nU 1 (#000000)\nL 1 (#000000)\nU 1 (#000000)\n";
+        assert_eq!(
+            area(data),
+            Err(String::from("The dig plan crosses itself around (1, 1)"))
+        );
+
+        // Going back over the previous segment
+        let data = "R 2 (#000000)\nL 1 (#000000)\nD 1 (#000000)\nL 1 (#000000)\nU 1 (#000000)\n";
+        assert!(area(data).is_err());
+    }
+
+    #[test]
+    fn open_trench_is_rejected() {
+        assert!(area("R 2 (#000000)\nD 2 (#000000)\n").is_err());
+    }
+
+    #[test]
+    fn solve_both_parts_of_example() {
+        assert_eq!(
+            solve(include_str!("data/day18/ex1")),
+            Ok((62, 952408144115))
+        );
+    }
+
+    #[test]
+    fn invalid_direction_nibble_is_reported() {
+        let data = "R 6 (#70c710)\nD 5 (#0dc571)\nL 2 (#70c71X)\n";
+
+        assert_eq!(
+            parse_2(data).err(),
+            Some(String::from("Invalid direction nibble 'X' on line 3"))
+        );
+    }
 }
@@ -27,6 +27,10 @@ use nom::{
 };
 use std::{
     collections::HashMap,
+    fmt::{
+        Display,
+        Formatter,
+    },
     ops::Range,
 };
 
@@ -36,6 +40,18 @@ enum Result {
     Rejected,
 }
 
+impl Display for Result {
+    fn fmt(
+        &self,
+        f: &mut Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            Result::Accepted => f.write_str("A"),
+            Result::Rejected => f.write_str("R"),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 enum Action<'a> {
     Result(Result),
@@ -52,6 +68,18 @@ impl<'a> Action<'a> {
     }
 }
 
+impl<'a> Display for Action<'a> {
+    fn fmt(
+        &self,
+        f: &mut Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            Action::Result(result) => write!(f, "{}", result),
+            Action::MoveTo(label) => f.write_str(label),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 enum Operation {
     LessThan,
@@ -67,6 +95,18 @@ impl Operation {
     }
 }
 
+impl Display for Operation {
+    fn fmt(
+        &self,
+        f: &mut Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            Operation::LessThan => f.write_str("<"),
+            Operation::MoreThan => f.write_str(">"),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 enum Condition<'a> {
     All {
@@ -107,7 +147,7 @@ impl<'a> Condition<'a> {
     fn process(
         &self,
         data: &Data<'a>,
-    ) -> Option<Action> {
+    ) -> Option<Action<'_>> {
         match self {
             Condition::All { action } => Some(*action),
             Condition::Operation {
@@ -130,15 +170,17 @@ impl<'a> Condition<'a> {
         }
     }
 
+    // Splits the data in the part matching the condition and the part that does not.
+    // The data is only cloned when the condition actually splits it in two.
     fn process_range(
         &self,
-        data: &DataRange<'a>,
-    ) -> ConditionRangeResult {
+        mut data: DataRange<'a>,
+    ) -> ConditionRangeResult<'a> {
         match self {
             Condition::All { action } => {
                 ConditionRangeResult {
                     action: *action,
-                    matched: (*data).clone(),
+                    matched: data,
                     unmatched: DataRange::empty(),
                 }
             }
@@ -149,50 +191,42 @@ impl<'a> Condition<'a> {
                 action,
             } => {
                 if let Some(range) = data.values.get(field) {
-                    match operation {
+                    let (matched_range, unmatched_range) = match operation {
                         Operation::LessThan => {
-                            if range.start < *threshold {
-                                let mut matched = (*data).clone();
-                                matched.values.insert(field, range.start..*threshold);
-
-                                let mut unmatched = (*data).clone();
-                                unmatched.values.insert(field, *threshold..range.end);
-
-                                ConditionRangeResult {
-                                    action: *action,
-                                    matched,
-                                    unmatched,
-                                }
-                            } else {
-                                ConditionRangeResult {
-                                    action: Action::Result(Result::Rejected), // Don't care
-                                    matched: DataRange::empty(),
-                                    unmatched: (*data).clone(),
-                                }
-                            }
+                            (
+                                range.start..(*threshold).min(range.end),
+                                (*threshold).max(range.start)..range.end,
+                            )
                         }
                         Operation::MoreThan => {
-                            if range.end > *threshold {
-                                let mut matched = (*data).clone();
-                                matched.values.insert(field, (*threshold + 1)..range.end);
-
-                                let mut unmatched = (*data).clone();
-                                unmatched
-                                    .values
-                                    .insert(field, range.start..(*threshold + 1));
-
-                                ConditionRangeResult {
-                                    action: *action,
-                                    matched,
-                                    unmatched,
-                                }
-                            } else {
-                                ConditionRangeResult {
-                                    action: Action::Result(Result::Rejected), // Don't care
-                                    matched: DataRange::empty(),
-                                    unmatched: (*data).clone(),
-                                }
-                            }
+                            (
+                                (*threshold + 1).max(range.start)..range.end,
+                                range.start..(*threshold + 1).min(range.end),
+                            )
+                        }
+                    };
+
+                    if matched_range.is_empty() {
+                        ConditionRangeResult {
+                            action: Action::Result(Result::Rejected), // Don't care
+                            matched: DataRange::empty(),
+                            unmatched: data,
+                        }
+                    } else if unmatched_range.is_empty() {
+                        ConditionRangeResult {
+                            action: *action,
+                            matched: data,
+                            unmatched: DataRange::empty(),
+                        }
+                    } else {
+                        let mut matched = data.clone();
+                        matched.values.insert(field, matched_range);
+                        data.values.insert(field, unmatched_range);
+
+                        ConditionRangeResult {
+                            action: *action,
+                            matched,
+                            unmatched: data,
                         }
                     }
                 } else {
@@ -207,6 +241,23 @@ impl<'a> Condition<'a> {
     }
 }
 
+impl<'a> Display for Condition<'a> {
+    fn fmt(
+        &self,
+        f: &mut Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            Condition::All { action } => write!(f, "{}", action),
+            Condition::Operation {
+                field,
+                operation,
+                threshold,
+                action,
+            } => write!(f, "{}{}{}:{}", field, operation, threshold, action),
+        }
+    }
+}
+
 #[derive(Debug)]
 struct ConditionRangeResult<'a> {
     action: Action<'a>,
@@ -266,6 +317,14 @@ impl<'a> Conditions<'a> {
         Result::Rejected
     }
 
+    // Verdict for each part, in the same order as the parts
+    fn classify_all(
+        &self,
+        parts: &[Data<'a>],
+    ) -> Vec<Result> {
+        parts.iter().map(|part| self.process(part)).collect()
+    }
+
     fn process_range(
         &'a self,
         data: DataRange<'a>,
@@ -283,7 +342,7 @@ impl<'a> Conditions<'a> {
                         action,
                         matched,
                         unmatched,
-                    } = condition.process_range(&data);
+                    } = condition.process_range(data);
                     match action {
                         Action::Result(result) => {
                             match result {
@@ -306,6 +365,71 @@ impl<'a> Conditions<'a> {
 
         results
     }
+
+    // Accepted ranges, one for each path through the workflows leading to an acceptance
+    fn accept_ranges(&'a self) -> Vec<DataRange<'a>> {
+        self.process_range(DataRange::full())
+            .into_iter()
+            .filter(|range| !range.is_empty())
+            .collect()
+    }
+
+    fn accept_paths(&'a self) -> usize {
+        self.accept_ranges().len()
+    }
+
+    // Number of accepted combinations among the given ranges
+    fn accepted_count(
+        &'a self,
+        data: DataRange<'a>,
+    ) -> u64 {
+        self.process_range(data)
+            .iter()
+            .map(|r| {
+                r.values
+                    .values()
+                    .map(|range| range.len() as u64)
+                    .product::<u64>()
+            })
+            .sum()
+    }
+
+    // Number of accepted combinations of the other fields when `field` has the given value
+    fn accepted_count_with(
+        &'a self,
+        field: &str,
+        value: u32,
+    ) -> u64 {
+        let mut data = DataRange::full();
+        match data.values.get_mut(field) {
+            Some(range) if range.contains(&value) => *range = value..(value + 1),
+            // No part has this field, or this rating
+            _ => return 0,
+        }
+
+        self.accepted_count(data)
+    }
+}
+
+// One workflow per line, sorted by name as the map has no order
+impl<'a> Display for Conditions<'a> {
+    fn fmt(
+        &self,
+        f: &mut Formatter<'_>,
+    ) -> std::fmt::Result {
+        let mut names = self.conditions.keys().collect::<Vec<_>>();
+        names.sort();
+
+        for name in names {
+            let conditions = self.conditions[name]
+                .iter()
+                .map(|condition| condition.to_string())
+                .collect::<Vec<_>>();
+            writeln!(f, "{}{{{}}}", name, conditions.join(","))?;
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -345,12 +469,22 @@ impl<'a> DataRange<'a> {
         }
     }
 
+    // Every rating from 1 to 4000 for each field
+    fn full() -> Self {
+        DataRange {
+            values: ["x", "m", "a", "s"]
+                .into_iter()
+                .map(|field| (field, 1..4001))
+                .collect(),
+        }
+    }
+
     fn is_empty(&self) -> bool {
         self.values.is_empty() || self.values.values().any(|range| range.is_empty())
     }
 }
 
-fn parse(input: &str) -> IResult<&str, (Conditions, Vec<Data>)> {
+fn parse(input: &str) -> IResult<&str, (Conditions<'_>, Vec<Data<'_>>)> {
     all_consuming(separated_pair(
         Conditions::parse,
         line_ending,
@@ -364,15 +498,17 @@ fn first(
 ) {
     let (_, (conditions, data)) = parse(data).finish().unwrap();
 
+    let verdicts = conditions.classify_all(&data);
     let sum: u32 = data
         .iter()
-        .filter(|data| {
-            match conditions.process(data) {
+        .zip(verdicts)
+        .filter(|(_, verdict)| {
+            match verdict {
                 Result::Accepted => true,
                 Result::Rejected => false,
             }
         })
-        .map(|data| data.values.values().sum::<u32>())
+        .map(|(data, _)| data.values.values().sum::<u32>())
         .sum();
 
     println!("[{}] Sum of accepted parts {}", name, sum);
@@ -384,14 +520,35 @@ fn second(
 ) {
     let (_, (conditions, _)) = parse(data).finish().unwrap();
 
-    let mut values = HashMap::new();
-    values.insert("x", 1..4001);
-    values.insert("m", 1..4001);
-    values.insert("a", 1..4001);
-    values.insert("s", 1..4001);
+    let combinations = conditions.accepted_count(DataRange::full());
+
+    println!("[{}] Total combinations working: {}", name, combinations);
+}
+
+fn second_with(
+    name: &str,
+    data: &str,
+    field: &str,
+    value: u32,
+) {
+    let (_, (conditions, _)) = parse(data).finish().unwrap();
+
+    let combinations = conditions.accepted_count_with(field, value);
+
+    println!(
+        "[{}] Combinations working with {}={}: {}",
+        name, field, value, combinations
+    );
+}
 
-    let result_ranges = conditions.process_range(DataRange { values });
-    let combinations: u64 = result_ranges
+fn paths(
+    name: &str,
+    data: &str,
+) {
+    let (_, (conditions, _)) = parse(data).finish().unwrap();
+
+    let accept_ranges = conditions.accept_ranges();
+    let smallest = accept_ranges
         .iter()
         .map(|r| {
             r.values
@@ -399,9 +556,23 @@ fn second(
                 .map(|range| range.len() as u64)
                 .product::<u64>()
         })
-        .sum();
+        .min()
+        .unwrap_or_default();
+
+    println!(
+        "[{}] Paths to acceptance: {}, smallest accepting {} combinations",
+        name,
+        conditions.accept_paths(),
+        smallest
+    );
+}
 
-    println!("[{}] Total combinations working: {}", name, combinations);
+fn workflows(
+    name: &str,
+    data: &str,
+) {
+    let (_, (conditions, _)) = parse(data).finish().unwrap();
+    println!("[{}] Workflows:\n{}", name, conditions);
 }
 
 pub fn run() {
@@ -409,4 +580,134 @@ pub fn run() {
     first("First", include_str!("data/day19/input")); // 323 625
     second("Second example", include_str!("data/day19/ex1")); // 167 409 079 868 000
     second("Second", include_str!("data/day19/input")); // 127 447 746 739 409
+    second_with("Second example", include_str!("data/day19/ex1"), "x", 1000); // 46 551 252 560
+    paths("Paths example", include_str!("data/day19/ex1")); // 9 / 8 167 885 440 000
+    workflows("Workflows example", include_str!("data/day19/ex1")); // The 11 workflows, sorted by name
+    paths("Paths", include_str!("data/day19/input")); // 586 / 924 161 346
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepted_counts_per_value_add_up() {
+        let (_, (conditions, _)) = parse(include_str!("data/day19/ex1")).finish().unwrap();
+
+        let total: u64 = (1..4001)
+            .map(|value| conditions.accepted_count_with("m", value))
+            .sum();
+        assert_eq!(total, 167409079868000);
+        assert_eq!(conditions.accepted_count_with("z", 1000), 0);
+
+        // Ratings go from 1 to 4000
+        assert_ne!(conditions.accepted_count_with("x", 4000), 0);
+        assert_eq!(conditions.accepted_count_with("x", 4001), 0);
+        assert_eq!(conditions.accepted_count_with("x", 5000), 0);
+        assert_eq!(conditions.accepted_count_with("x", 0), 0);
+    }
+
+    #[test]
+    fn accept_paths_of_example() {
+        let (_, (conditions, _)) = parse(include_str!("data/day19/ex1")).finish().unwrap();
+
+        // Each of the 9 accepting rules of the example is reached by a single path
+        assert_eq!(conditions.accept_paths(), 9);
+        let total: u64 = conditions
+            .accept_ranges()
+            .into_iter()
+            .map(|range| conditions.accepted_count(range))
+            .sum();
+        assert_eq!(total, 167409079868000);
+    }
+
+    fn x_range(range: Range<u32>) -> DataRange<'static> {
+        DataRange {
+            values: HashMap::from([("x", range)]),
+        }
+    }
+
+    fn x_condition(
+        operation: Operation,
+        threshold: u32,
+    ) -> Condition<'static> {
+        Condition::Operation {
+            field: "x",
+            operation,
+            threshold,
+            action: Action::MoveTo("next"),
+        }
+    }
+
+    #[test]
+    fn threshold_below_range_start_matches_nothing() {
+        // x < 100 on 100..200
+        let result = x_condition(Operation::LessThan, 100).process_range(x_range(100..200));
+
+        assert!(matches!(result.action, Action::Result(Result::Rejected)));
+        assert!(result.matched.is_empty());
+        assert_eq!(result.unmatched.values["x"], 100..200);
+
+        // x > 99 on 100..200 is the opposite
+        let result = x_condition(Operation::MoreThan, 99).process_range(x_range(100..200));
+
+        assert!(matches!(result.action, Action::MoveTo("next")));
+        assert_eq!(result.matched.values["x"], 100..200);
+        assert!(result.unmatched.is_empty());
+    }
+
+    #[test]
+    fn threshold_above_range_end_matches_nothing() {
+        // x > 199 on 100..200
+        let result = x_condition(Operation::MoreThan, 199).process_range(x_range(100..200));
+
+        assert!(matches!(result.action, Action::Result(Result::Rejected)));
+        assert!(result.matched.is_empty());
+        assert_eq!(result.unmatched.values["x"], 100..200);
+
+        // x < 200 on 100..200 is the opposite
+        let result = x_condition(Operation::LessThan, 200).process_range(x_range(100..200));
+
+        assert!(matches!(result.action, Action::MoveTo("next")));
+        assert_eq!(result.matched.values["x"], 100..200);
+        assert!(result.unmatched.is_empty());
+    }
+
+    #[test]
+    fn absent_field_matches_nothing() {
+        let data = DataRange {
+            values: HashMap::from([("m", 100..200)]),
+        };
+        let result = x_condition(Operation::LessThan, 150).process_range(data);
+
+        assert!(matches!(result.action, Action::Result(Result::Rejected)));
+        assert!(result.matched.is_empty());
+        assert!(result.unmatched.is_empty());
+    }
+
+    #[test]
+    fn threshold_inside_range_splits_it() {
+        let result = x_condition(Operation::LessThan, 150).process_range(x_range(100..200));
+        assert_eq!(result.matched.values["x"], 100..150);
+        assert_eq!(result.unmatched.values["x"], 150..200);
+
+        let result = x_condition(Operation::MoreThan, 150).process_range(x_range(100..200));
+        assert_eq!(result.matched.values["x"], 151..200);
+        assert_eq!(result.unmatched.values["x"], 100..151);
+    }
+
+    #[test]
+    fn display_workflows_as_parsed() {
+        let input = include_str!("data/day19/ex1");
+        let (_, (conditions, _)) = parse(input).finish().unwrap();
+
+        let mut workflows = input
+            .lines()
+            .take_while(|line| !line.is_empty())
+            .collect::<Vec<_>>();
+        workflows.sort();
+
+        assert_eq!(conditions.to_string(), workflows.join("\n") + "\n");
+        assert_eq!(conditions.conditions["px"][0].to_string(), "a<2006:qkq");
+    }
 }
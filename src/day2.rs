@@ -14,7 +14,7 @@ use nom::{
     },
     multi::{
         many1,
-        separated_list1,
+        separated_list0,
     },
     sequence::{
         terminated,
@@ -40,7 +40,8 @@ impl Game {
                     character::complete::u16,
                     space0,
                     tag(":"),
-                    separated_list1(tag(";"), Dices::parse),
+                    // A game without any set is parsed, it is always possible and has no power
+                    separated_list0(tag(";"), Dices::parse),
                 )),
                 |(_, _, id, _, _, sets)| Game { id, sets },
             ),
@@ -147,41 +148,45 @@ fn parse_games(input: &str) -> IResult<&str, Vec<Game>> {
     all_consuming(many1(terminated(Game::parse, opt(line_ending))))(input)
 }
 
-fn first(
-    name: &str,
-    data: &str,
-) {
-    let global = Dices {
-        blue: 14,
-        green: 13,
-        red: 12,
-    };
-
-    let (_, games) = parse_games(data).finish().unwrap();
-    let sum_possible_games: u64 = games
+// Checks the game ids are 1, 2, 3, ... as a skipped or repeated id usually means a line was misread
+fn check_ids(games: &[Game]) -> Result<(), String> {
+    match games
         .iter()
-        .filter_map(|game| {
-            let has_impossible_set = game.sets.iter().any(|dice| {
-                dice.blue > global.blue || dice.green > global.green || dice.red > global.red
-            });
-
-            if has_impossible_set {
-                None
-            } else {
-                Some(game.id as u64)
-            }
-        })
-        .sum();
+        .zip(1_u16..)
+        .find(|(game, expected_id)| game.id != *expected_id)
+    {
+        Some((game, expected_id)) => {
+            Err(format!(
+                "Expected game {} but found game {}",
+                expected_id, game.id
+            ))
+        }
+        None => Ok(()),
+    }
+}
 
-    println!("[{}] Sum of possible games: '{}'", name, sum_possible_games);
+// Ids of the games where every set satisfies the predicate
+fn possible_games<F: Fn(&Dices) -> bool>(
+    games: &[Game],
+    set_ok: F,
+) -> Vec<u16> {
+    games
+        .iter()
+        .filter(|game| game.sets.iter().all(&set_ok))
+        .map(|game| game.id)
+        .collect()
 }
 
-fn second(
-    name: &str,
-    data: &str,
-) {
-    let (_, games) = parse_games(data).finish().unwrap();
-    let sum_powers: u64 = games
+fn possible_sum(games: &[Game]) -> u64 {
+    // The bag contains 12 red, 13 green and 14 blue cubes
+    possible_games(games, |d| d.red <= 12 && d.green <= 13 && d.blue <= 14)
+        .iter()
+        .map(|id| *id as u64)
+        .sum()
+}
+
+fn power_sum(games: &[Game]) -> u64 {
+    games
         .iter()
         .map(|game| {
             game.sets
@@ -189,8 +194,38 @@ fn second(
                 .fold(Dices::default(), |a, b| a.max(b))
                 .power()
         })
-        .sum();
+        .sum()
+}
 
+// Parses the games once and computes both the sum of possible games and the sum of powers.
+// Hand-crafted inputs may not number their games sequentially, so checking the ids is optional.
+fn solve(
+    data: &str,
+    validate_ids: bool,
+) -> Result<(u64, u64), String> {
+    let (_, games) = parse_games(data)
+        .finish()
+        .map_err(|error| format!("{:?}", error))?;
+    if validate_ids {
+        check_ids(&games)?;
+    }
+
+    Ok((possible_sum(&games), power_sum(&games)))
+}
+
+fn first(
+    name: &str,
+    data: &str,
+) {
+    let (sum_possible_games, _) = solve(data, false).unwrap();
+    println!("[{}] Sum of possible games: '{}'", name, sum_possible_games);
+}
+
+fn second(
+    name: &str,
+    data: &str,
+) {
+    let (_, sum_powers) = solve(data, false).unwrap();
     println!("[{}] Sum of powers: '{}'", name, sum_powers);
 }
 
@@ -200,3 +235,66 @@ pub fn run() {
     second("Second example", include_str!("data/day2/ex1")); // 2286
     second("Second", include_str!("data/day2/input")); // 67363
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solve_both_parts_at_once() {
+        assert_eq!(
+            solve(include_str!("data/day2/input"), true),
+            Ok((2528, 67363))
+        );
+    }
+
+    #[test]
+    fn possible_games_with_predicate() {
+        let (_, games) = parse_games(include_str!("data/day2/ex1")).finish().unwrap();
+
+        assert_eq!(
+            possible_games(&games, |d| d.red <= 12 && d.green <= 13 && d.blue <= 14),
+            vec![1, 2, 5]
+        );
+        assert_eq!(possible_games(&games, |d| d.blue <= 4), vec![2, 5]);
+        assert_eq!(possible_games(&games, |_| true), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn single_set_game() {
+        let (_, games) = parse_games("Game 1: 3 blue, 4 red\nGame 2: 15 blue\n")
+            .finish()
+            .unwrap();
+
+        assert_eq!(games[0].sets.len(), 1);
+        assert_eq!(games[1].sets.len(), 1);
+        assert_eq!(possible_sum(&games), 1);
+        assert_eq!(power_sum(&games), 0);
+    }
+
+    #[test]
+    fn game_without_sets() {
+        let data = "Game 1:\nGame 2: 1 blue, 2 green, 3 red\n";
+        let (_, games) = parse_games(data).finish().unwrap();
+
+        assert!(games[0].sets.is_empty());
+        assert_eq!(solve(data, true), Ok((3, 6)));
+    }
+
+    #[test]
+    fn skipped_or_repeated_ids_are_reported() {
+        let data = "Game 1: 1 blue\nGame 3: 2 blue\n";
+        assert_eq!(
+            solve(data, true),
+            Err(String::from("Expected game 2 but found game 3"))
+        );
+        assert_eq!(solve(data, false), Ok((4, 0)));
+        assert!(solve("Game 1: 1 purple\n", false).is_err());
+
+        let data = "Game 1: 1 blue\nGame 2: 2 blue\nGame 2: 3 blue\nGame 4: 4 blue\n";
+        assert_eq!(
+            solve(data, true),
+            Err(String::from("Expected game 3 but found game 2"))
+        );
+    }
+}
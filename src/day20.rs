@@ -5,7 +5,7 @@ use nom::{
         tag,
     },
     character::complete::{
-        alpha1,
+        alphanumeric1,
         char,
         line_ending,
         space1,
@@ -29,7 +29,11 @@ use nom::{
 };
 use std::{
     cell::RefCell,
-    collections::HashMap,
+    collections::{
+        HashMap,
+        HashSet,
+        VecDeque,
+    },
 };
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -130,7 +134,23 @@ impl<'a> Component<'a> {
         }
     }
 
-    fn base(&self) -> &BaseComponent {
+    // Back to the initial state: flip-flops off, conjunctions remembering low pulses
+    fn reset(&self) {
+        match self {
+            Component::FlipFlop { state, .. } => {
+                state.replace(FlipFlopState::Off);
+            }
+            Component::Conjunction { states, .. } => {
+                states
+                    .borrow_mut()
+                    .values_mut()
+                    .for_each(|pulse| *pulse = Pulse::Low);
+            }
+            Component::Broadcaster(_) | Component::Output(_) => {}
+        }
+    }
+
+    fn base(&self) -> &BaseComponent<'_> {
         match self {
             Component::Broadcaster(base) => base,
             Component::FlipFlop { base, .. } => base,
@@ -166,17 +186,17 @@ impl<'a> Component<'a> {
             }
 
             Component::Conjunction { base, states } => {
-                // When a pulse is received, the conjunction module first updates its memory for that input.
-                states.borrow_mut().insert(from, pulse);
+                let all_high = {
+                    let mut states = states.borrow_mut();
+
+                    // When a pulse is received, the conjunction module first updates its memory for that input.
+                    states.insert(from, pulse);
+
+                    states.values().all(|pulse| pulse == &Pulse::High)
+                };
 
                 // Then, if it remembers high pulses for all inputs, it sends a low pulse; otherwise, it sends a high pulse.
-                base.propagate_to_children(
-                    if states.borrow().values().all(|pulse| pulse == &Pulse::High) {
-                        Pulse::Low
-                    } else {
-                        Pulse::High
-                    },
-                )
+                base.propagate_to_children(if all_high { Pulse::Low } else { Pulse::High })
             }
             Component::Output(_) => vec![],
         }
@@ -206,7 +226,7 @@ impl<'a> RawComponent<'a> {
                     value(ComponentType::FlipFlop, char('%')),
                     value(ComponentType::Conjunction, char('&')),
                 )),
-                alpha1,
+                alphanumeric1,
             )),
         ));
 
@@ -227,172 +247,427 @@ impl<'a> RawComponent<'a> {
     }
 }
 
-fn parse(input: &str) -> IResult<&str, HashMap<&str, Component>> {
-    all_consuming(map(
-        many1(terminated(RawComponent::parse, line_ending)),
-        |raw_components| {
-            // Easy access to components
-            let label_to_children: HashMap<_, _> = raw_components
-                .iter()
-                .map(|raw_component| (raw_component.label, &raw_component.children))
-                .collect();
+fn parse_raw(input: &str) -> IResult<&str, Vec<RawComponent<'_>>> {
+    all_consuming(many1(terminated(RawComponent::parse, line_ending)))(input)
+}
+
+fn parse(input: &str) -> Result<HashMap<&str, Component<'_>>, String> {
+    let (_, raw_components) = parse_raw(input)
+        .finish()
+        .map_err(|error| format!("{:?}", error))?;
+
+    build(&raw_components)
+}
+
+// Validates the raw components and links them to their parents
+fn build<'a>(
+    raw_components: &[RawComponent<'a>]
+) -> Result<HashMap<&'a str, Component<'a>>, String> {
+    // A second broadcaster would silently replace the first one.
+    // Modules that cannot be reached from the broadcaster are kept, but never receive any pulse.
+    let broadcasters = raw_components
+        .iter()
+        .filter(|raw_component| {
+            matches!(raw_component.component_type, ComponentType::Broadcaster)
+        })
+        .count();
+    if broadcasters > 1 {
+        return Err(format!("Expected a single broadcaster, found {}", broadcasters));
+    }
 
-            let label_to_parents = raw_components
+    // Easy access to components
+    let label_to_children: HashMap<_, _> = raw_components
+        .iter()
+        .map(|raw_component| (raw_component.label, &raw_component.children))
+        .collect();
+
+    let label_to_parents = raw_components
+        .iter()
+        .flat_map(|raw_component| {
+            raw_component
+                .children
                 .iter()
-                .flat_map(|raw_component| {
-                    raw_component
-                        .children
-                        .iter()
-                        .map(|child_label| (*child_label, raw_component.label))
+                .map(|child_label| (*child_label, raw_component.label))
+        })
+        .fold(HashMap::new(), |mut map, (child_label, parent_label)| {
+            map.entry(child_label)
+                .and_modify(|parent_labels: &mut Vec<&str>| {
+                    parent_labels.push(parent_label)
                 })
-                .fold(HashMap::new(), |mut map, (child_label, parent_label)| {
-                    map.entry(child_label)
-                        .and_modify(|parent_labels: &mut Vec<&str>| {
-                            parent_labels.push(parent_label)
-                        })
-                        .or_insert_with(|| vec![parent_label]);
-                    map
-                });
-
-            // Build actual components
-            let mut components = HashMap::<&str, Component>::new();
-
-            // Find the component without children
-            raw_components
+                .or_insert_with(|| vec![parent_label]);
+            map
+        });
+
+    // Build actual components
+    let mut components = HashMap::<&str, Component>::new();
+
+    // Find the components without definition, such as rx, and make them outputs
+    // A module may have several of them as children, and they may have several parents
+    raw_components
+        .iter()
+        .flat_map(|raw_component| {
+            raw_component
+                .children
                 .iter()
-                .filter_map(|raw_component| {
-                    raw_component
-                        .children
-                        .iter()
-                        .find(|label| !label_to_children.contains_key(*label))
-                })
-                .for_each(|output_label| {
-                    components.insert(
-                        output_label,
-                        Component::output(
-                            label_to_parents
-                                .get(output_label)
-                                .cloned()
-                                .unwrap_or_default(),
-                        ),
-                    );
-                });
-
-            for RawComponent {
-                component_type,
-                label,
-                children,
-            } in &raw_components
-            {
-                let parents = label_to_parents
-                    .get(label)
-                    .cloned()
-                    .unwrap_or_default();
-
-                match component_type {
-                    ComponentType::Broadcaster => {
-                        components.insert(label, Component::broadcaster( children.clone()))
-                    }
-                    ComponentType::FlipFlop => {
-                        components.insert(label, Component::flip_flop(parents, children.clone()))
-                    }
-                    ComponentType::Conjunction => {
-                        components.insert(label, Component::conjunction(parents, children.clone()))
-                    }
-                };
+                .filter(|label| !label_to_children.contains_key(*label))
+        })
+        .for_each(|output_label| {
+            components.insert(
+                output_label,
+                Component::output(
+                    label_to_parents
+                        .get(output_label)
+                        .cloned()
+                        .unwrap_or_default(),
+                ),
+            );
+        });
+
+    for RawComponent {
+        component_type,
+        label,
+        children,
+    } in raw_components
+    {
+        let parents = label_to_parents
+            .get(label)
+            .cloned()
+            .unwrap_or_default();
+
+        match component_type {
+            ComponentType::Broadcaster => {
+                components.insert(label, Component::broadcaster(children.clone()))
+            }
+            ComponentType::FlipFlop => {
+                components.insert(label, Component::flip_flop(parents, children.clone()))
             }
+            ComponentType::Conjunction => {
+                components.insert(label, Component::conjunction(parents, children.clone()))
+            }
+        };
+    }
 
-            components
-        },
-    ))(input)
+    Ok(components)
 }
 
-fn push_button(
-    components: &mut HashMap<&str, Component>,
-    mut on_pulse: impl FnMut(&str, &str, Pulse),
-) {
-    let mut children_pulses = vec![("button", vec![("broadcaster", Pulse::Low)])];
+// Simulates the circuit one pulse delivery at a time
+struct PulseSim<'a> {
+    components: HashMap<&'a str, Component<'a>>,
+    queue: VecDeque<(&'a str, &'a str, Pulse)>,
+}
 
-    while !children_pulses.is_empty() {
-        let mut next_children_pulses = Vec::new();
+impl<'a> PulseSim<'a> {
+    fn new(components: HashMap<&'a str, Component<'a>>) -> Self {
+        PulseSim {
+            components,
+            queue: VecDeque::new(),
+        }
+    }
 
-        for (parent_label, pulses) in children_pulses {
-            for (child_label, pulse) in pulses {
-                on_pulse(parent_label, child_label, pulse);
+    // Delivers the next pending pulse, returning it as (from, to, pulse)
+    fn step(&mut self) -> Option<(&'a str, &'a str, Pulse)> {
+        let (parent_label, child_label, pulse) = self.queue.pop_front()?;
 
-                next_children_pulses.push((
-                    child_label,
-                    components
-                        .get_mut(child_label)
-                        .map(|child| child.receive(parent_label, pulse))
-                        .unwrap_or_default(),
-                ))
-            }
+        let children_pulses = self
+            .components
+            .get_mut(child_label)
+            .map(|child| child.receive(parent_label, pulse))
+            .unwrap_or_default();
+
+        for (next_child_label, next_pulse) in children_pulses {
+            self.queue
+                .push_back((child_label, next_child_label, next_pulse));
         }
 
-        children_pulses = next_children_pulses;
+        Some((parent_label, child_label, pulse))
+    }
+
+    // Enqueues a pulse from any module to any other, without delivering it
+    fn send(
+        &mut self,
+        from: &'a str,
+        to: &'a str,
+        pulse: Pulse,
+    ) {
+        self.queue.push_back((from, to, pulse));
+    }
+
+    // Enqueues the initial low pulse of a button press, to be delivered with `step`
+    fn press_button(&mut self) {
+        self.send("button", "broadcaster", Pulse::Low);
+    }
+
+    // Sends a pulse from any module to any other, then delivers every pulse it triggers
+    fn inject(
+        &mut self,
+        from: &'a str,
+        to: &'a str,
+        pulse: Pulse,
+        mut on_pulse: impl FnMut(&str, &str, Pulse),
+    ) {
+        self.send(from, to, pulse);
+
+        while let Some((parent_label, child_label, pulse)) = self.step() {
+            on_pulse(parent_label, child_label, pulse);
+        }
+    }
+
+    fn push_button(
+        &mut self,
+        on_pulse: impl FnMut(&str, &str, Pulse),
+    ) {
+        self.inject("button", "broadcaster", Pulse::Low, on_pulse);
+    }
+
+    // Puts every module back in its initial state, as if the circuit was parsed again
+    fn reset(&mut self) {
+        self.queue.clear();
+        self.components.values().for_each(Component::reset);
+    }
+
+    // Number of high and low pulses sent over several button presses
+    fn count_pulses(
+        &mut self,
+        presses: usize,
+    ) -> (u64, u64) {
+        let mut high = 0_u64;
+        let mut low = 0_u64;
+        for _ in 0..presses {
+            self.push_button(|_, _, pulse| {
+                match pulse {
+                    Pulse::High => high += 1,
+                    Pulse::Low => low += 1,
+                }
+            });
+        }
+
+        (high, low)
     }
 }
 
+// Every pulse sent during a single button press, as (from, to, pulse), in delivery order
+fn record_press<'a>(components: HashMap<&'a str, Component<'a>>) -> Vec<(String, String, Pulse)> {
+    let mut sim = PulseSim::new(components);
+    let mut pulses = Vec::new();
+    sim.push_button(|from, to, pulse| pulses.push((String::from(from), String::from(to), pulse)));
+    pulses
+}
+
+// Readable view of the flip-flops and conjunctions state, keyed by label
+// Conjunctions memories are listed as `input:Pulse`, sorted by input
+fn snapshot(components: &HashMap<&str, Component>) -> HashMap<String, String> {
+    components
+        .iter()
+        .filter_map(|(label, component)| {
+            let state = match component {
+                Component::FlipFlop { state, .. } => format!("{:?}", state.borrow()),
+                Component::Conjunction { states, .. } => {
+                    let mut memory: Vec<_> = states
+                        .borrow()
+                        .iter()
+                        .map(|(input, pulse)| format!("{}:{:?}", input, pulse))
+                        .collect();
+                    memory.sort();
+                    memory.join(",")
+                }
+                Component::Broadcaster(_) | Component::Output(_) => return None,
+            };
+
+            Some((String::from(*label), state))
+        })
+        .collect()
+}
+
 fn first(
     name: &str,
     data: &str,
 ) {
-    let (_, mut components) = parse(data).finish().unwrap();
-
-    let mut high = 0_u32;
-    let mut low = 0_u32;
-    for _ in 0..1000 {
-        push_button(&mut components, |_, _, pulse| {
-            match pulse {
-                Pulse::High => high += 1,
-                Pulse::Low => low += 1,
+    let components = parse(data).unwrap();
+    let mut sim = PulseSim::new(components);
+
+    let (high, low) = sim.count_pulses(1000);
+
+    println!("[{}] H{} | L{} | P{}", name, high, low, high * low);
+}
+
+// Walks back from `target` to find the modules whose pulses must all happen during the same press
+// for `target` to receive a low pulse. This assumes that:
+// - `target` has a single input, otherwise any input could trigger it on its own
+// - the path back is made of inverters (single input conjunctions), up to a conjunction with
+//   several inputs, which sends a low pulse once all its inputs sent a high pulse
+fn cycle_watchers(
+    components: &HashMap<&str, Component>,
+    target: &str,
+) -> Result<Vec<(String, Pulse)>, String> {
+    let parents = |label: &str| {
+        components
+            .get(label)
+            .map(|component| component.base().parents.clone())
+            .ok_or_else(|| format!("Unknown module {}", label))
+    };
+
+    let target_parents = parents(target)?;
+    if target_parents.len() != 1 {
+        return Err(format!(
+            "Expected {} to have a single input, found {}",
+            target,
+            target_parents.len()
+        ));
+    }
+
+    let mut label = target_parents[0];
+    let mut pulse = Pulse::Low;
+    // Inverters may feed each other, in which case the walk would never end
+    let mut visited = HashSet::new();
+    loop {
+        if !visited.insert(label) {
+            return Err(format!("The inverters before {} loop through {}", target, label));
+        }
+
+        match (&components[label], pulse) {
+            (Component::Conjunction { base, .. }, Pulse::Low) if base.parents.len() > 1 => {
+                return Ok(base
+                    .parents
+                    .iter()
+                    .map(|parent| (String::from(*parent), Pulse::High))
+                    .collect());
             }
-        });
+            (Component::Conjunction { base, .. }, _) if base.parents.len() == 1 => {
+                // An inverter sends the opposite of what it receives
+                label = base.parents[0];
+                pulse = match pulse {
+                    Pulse::High => Pulse::Low,
+                    Pulse::Low => Pulse::High,
+                };
+            }
+            _ => {
+                return Err(format!(
+                    "Cannot find the cycles making {} send a {:?} pulse",
+                    label, pulse
+                ))
+            }
+        }
     }
+}
 
-    println!("[{}] H{} | L{} | P{}", name, high, low, high * low);
+impl PulseSim<'_> {
+    // Number of button presses until `from` sends the given pulse, giving up after `max_presses`
+    fn presses_until(
+        &mut self,
+        from: &str,
+        pulse: Pulse,
+        max_presses: usize,
+    ) -> Option<usize> {
+        (1..=max_presses).find(|_| {
+            let mut sent = false;
+            self.press_button();
+            while let Some((parent_label, _, sent_pulse)) = self.step() {
+                sent |= parent_label == from && sent_pulse == pulse;
+            }
+            sent
+        })
+    }
+
+    // Presses needed for `target` to receive a low pulse, as the LCM of the cycles of the modules
+    // found by `cycle_watchers`. Each of these modules must send its pulse periodically, the first
+    // time being after a whole period, otherwise the LCM would not be the answer.
+    fn presses_until_low(
+        &mut self,
+        target: &str,
+    ) -> Result<usize, String> {
+        const MAX_PRESSES: usize = 100_000;
+
+        let watchers = cycle_watchers(&self.components, target)?;
+
+        watchers.iter().try_fold(1, |lcm, (label, pulse)| {
+            // Each cycle is computed on its own, from the initial state
+            self.reset();
+
+            let first = self.presses_until(label, *pulse, MAX_PRESSES);
+            let second = self.presses_until(label, *pulse, MAX_PRESSES);
+            match (first, second) {
+                (Some(first), Some(second)) if first == second => Ok(num::integer::lcm(lcm, first)),
+                _ => {
+                    Err(format!(
+                        "{} does not send a {:?} pulse periodically: {:?} then {:?} presses",
+                        label, pulse, first, second
+                    ))
+                }
+            }
+        })
+    }
 }
 
-fn second(
+fn presses_until_low(
+    data: &str,
+    target: &str,
+) -> Result<usize, String> {
+    let components = parse(data)?;
+
+    PulseSim::new(components).presses_until_low(target)
+}
+
+// Pulses of the first press, written `from -pulse-> to` as in the puzzle statement
+fn first_press(
     name: &str,
     data: &str,
 ) {
-    let (_, mut components) = parse(data).finish().unwrap();
+    let components = parse(data).unwrap();
+
+    for (from, to, pulse) in record_press(components) {
+        let pulse = match pulse {
+            Pulse::High => "high",
+            Pulse::Low => "low",
+        };
+        println!("[{}] {} -{}-> {}", name, from, pulse, to);
+    }
+}
 
-    // The input has rx as the output, that has a single conjunction parent
-    // This is unsafe
-    let rx_parent = String::from(components["rx"].base().parents[0]);
-    let rx_parent_parents_size = components[rx_parent.as_str()].base().parents.len();
+// State of the flip-flops and conjunctions after each of the first presses, sorted by label
+fn states_after_presses(
+    name: &str,
+    data: &str,
+    presses: usize,
+) {
+    let components = parse(data).unwrap();
+    let mut sim = PulseSim::new(components);
+
+    for press in 1..=presses {
+        sim.push_button(|_, _, _| {});
+        let mut states: Vec<_> = snapshot(&sim.components).into_iter().collect();
+        states.sort();
+        println!("[{}] After press {}: {:?}", name, press, states);
+    }
+}
 
-    // Now we want to get all the parents to send a high pulse and see when this happens
-    let mut high_pulse_at: HashMap<String, usize> = HashMap::new();
-    let mut index = 0_usize;
-    loop {
-        index += 1;
-
-        push_button(&mut components, |parent_label, child_label, pulse| {
-            match pulse {
-                Pulse::High => {
-                    if child_label == rx_parent {
-                        let parent_label = String::from(parent_label);
-                        high_pulse_at.entry(parent_label).or_insert(index);
-                    }
-                }
-                Pulse::Low => {}
-            }
-        });
+// Both parts on a single parsed circuit, reset in between
+// Part two is only solved if the circuit has an `rx` module
+fn solve(data: &str) -> (u64, Option<u64>) {
+    let components = parse(data).unwrap();
+    let has_rx = components.contains_key("rx");
+    let mut sim = PulseSim::new(components);
 
-        if rx_parent_parents_size == high_pulse_at.len() {
-            break;
-        }
-    }
+    let (high, low) = sim.count_pulses(1000);
+    sim.reset();
+
+    let presses = has_rx.then(|| sim.presses_until_low("rx").unwrap() as u64);
+    (high * low, presses)
+}
+
+fn second(
+    name: &str,
+    data: &str,
+) {
+    let presses = presses_until_low(data, "rx").unwrap();
+    println!("[{}] Low pulse at {:?}", name, presses);
+}
 
-    // The iteration as which rx receives a low pulse is the LCM of the indexes
-    let lcm = high_pulse_at
-        .values()
-        .fold(1, |lcm, index| num::integer::lcm(lcm, *index));
-    println!("[{}] Low pulse at {:?}", name, lcm);
+fn both(
+    name: &str,
+    data: &str,
+) {
+    let (product, presses) = solve(data);
+    println!("[{}] P{} | Low pulse at {:?}", name, product, presses);
 }
 
 pub fn run() {
@@ -400,4 +675,222 @@ pub fn run() {
     first("First example 2", include_str!("data/day20/ex2")); // H2750 | L4250 | P11687500
     first("First", include_str!("data/day20/input")); // H48760 | L18124 | P883726240
     second("Second", include_str!("data/day20/input")); // 211 712 400 442 661
+    both("Both", include_str!("data/day20/input")); // P883726240 | Some(211712400442661)
+    first_press("Press example 1", include_str!("data/day20/ex1")); // 12 pulses, as in data/day20/ex1_press
+    states_after_presses("States example 2", include_str!("data/day20/ex2"), 4); // Back to all Off / Low after press 4
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multiple_broadcasters_are_rejected() {
+        let data = "broadcaster -> a\n%a -> out\nbroadcaster -> b\n%b -> out\n";
+        assert_eq!(
+            parse(data).err(),
+            Some(String::from("Expected a single broadcaster, found 2"))
+        );
+    }
+
+    #[test]
+    fn undefined_modules_are_outputs() {
+        let data = "broadcaster -> a, b\n%a -> out, rx\n%b -> out\n";
+        let components = parse(data).unwrap();
+
+        assert_eq!(components.len(), 5);
+        assert!(matches!(components["out"], Component::Output(_)));
+        assert_eq!(components["out"].base().parents, vec!["a", "b"]);
+        assert!(matches!(components["rx"], Component::Output(_)));
+        assert_eq!(components["rx"].base().parents, vec!["a"]);
+    }
+
+    #[test]
+    fn labels_can_contain_digits() {
+        let data = "broadcaster -> a1\n&a1 -> b2\n%b2 -> a1, out\n";
+        let components = parse(data).unwrap();
+
+        assert!(matches!(components["a1"], Component::Conjunction { .. }));
+        assert_eq!(components["a1"].base().parents, vec!["broadcaster", "b2"]);
+        assert_eq!(components["b2"].base().parents, vec!["a1"]);
+        assert_eq!(components["out"].base().parents, vec!["b2"]);
+    }
+
+    #[test]
+    fn disjoint_circuit_never_receives_pulses() {
+        let data = "broadcaster -> a\n%a -> out\n%b -> c\n%c -> b\n";
+        let components = parse(data).unwrap();
+        let mut sim = PulseSim::new(components);
+
+        let mut receivers = Vec::new();
+        sim.push_button(|_, child_label, _| receivers.push(String::from(child_label)));
+        assert_eq!(receivers, vec!["broadcaster", "a", "out"]);
+    }
+
+    #[test]
+    fn flip_flop_ignores_injected_high_pulse() {
+        let components = parse(include_str!("data/day20/ex2")).unwrap();
+        let mut sim = PulseSim::new(components);
+
+        let mut pulses = Vec::new();
+        sim.inject("broadcaster", "a", Pulse::High, |from, to, pulse| {
+            pulses.push((String::from(from), String::from(to), pulse))
+        });
+        assert_eq!(
+            pulses,
+            vec![(String::from("broadcaster"), String::from("a"), Pulse::High)]
+        );
+        assert_eq!(snapshot(&sim.components)["a"], "Off");
+
+        let mut receivers = Vec::new();
+        sim.inject("broadcaster", "a", Pulse::Low, |_, to, _| {
+            receivers.push(String::from(to))
+        });
+        assert_eq!(
+            receivers,
+            vec!["a", "inv", "con", "b", "output", "con", "output"]
+        );
+        assert_eq!(snapshot(&sim.components)["a"], "On");
+    }
+
+    #[test]
+    fn step_through_a_press() {
+        let components = parse(include_str!("data/day20/ex1")).unwrap();
+        let mut sim = PulseSim::new(components);
+
+        assert_eq!(sim.step(), None);
+        sim.press_button();
+        assert_eq!(sim.step(), Some(("button", "broadcaster", Pulse::Low)));
+        assert_eq!(sim.step(), Some(("broadcaster", "a", Pulse::Low)));
+        assert_eq!(sim.step(), Some(("broadcaster", "b", Pulse::Low)));
+        assert_eq!(sim.step(), Some(("broadcaster", "c", Pulse::Low)));
+        assert_eq!(sim.step(), Some(("a", "b", Pulse::High)));
+        assert_eq!(sim.step(), Some(("b", "c", Pulse::High)));
+        assert_eq!(sim.step(), Some(("c", "inv", Pulse::High)));
+        assert_eq!(sim.step(), Some(("inv", "a", Pulse::Low)));
+        assert_eq!(sim.step(), Some(("a", "b", Pulse::Low)));
+        assert_eq!(sim.step(), Some(("b", "c", Pulse::Low)));
+        assert_eq!(sim.step(), Some(("c", "inv", Pulse::Low)));
+        assert_eq!(sim.step(), Some(("inv", "a", Pulse::High)));
+        assert_eq!(sim.step(), None);
+    }
+
+    #[test]
+    fn replay_recorded_press() {
+        // Recorded as `from -pulse-> to`, like in the puzzle statement
+        let expected: Vec<_> = include_str!("data/day20/ex1_press")
+            .lines()
+            .map(|line| {
+                let (from, rest) = line.split_once(" -").unwrap();
+                let (pulse, to) = rest.split_once("-> ").unwrap();
+                let pulse = match pulse {
+                    "low" => Pulse::Low,
+                    "high" => Pulse::High,
+                    _ => panic!("Invalid pulse '{}'", pulse),
+                };
+                (String::from(from), String::from(to), pulse)
+            })
+            .collect();
+
+        let components = parse(include_str!("data/day20/ex1")).unwrap();
+        assert_eq!(record_press(components), expected);
+    }
+
+    #[test]
+    fn reset_restores_the_initial_state() {
+        let components = parse(include_str!("data/day20/ex2")).unwrap();
+        let mut sim = PulseSim::new(components);
+        let initial = snapshot(&sim.components);
+
+        sim.count_pulses(3);
+        assert_ne!(snapshot(&sim.components), initial);
+        sim.reset();
+        assert_eq!(snapshot(&sim.components), initial);
+        assert_eq!(sim.count_pulses(1000), (2750, 4250));
+    }
+
+    #[test]
+    fn solve_both_parts() {
+        assert_eq!(solve(include_str!("data/day20/ex1")), (32000000, None));
+        assert_eq!(
+            solve(include_str!("data/day20/input")),
+            (883726240, Some(211712400442661))
+        );
+    }
+
+    #[test]
+    fn cycles_must_go_through_a_conjunction() {
+        // rx is fed by a flip-flop, whose pulses do not come from aligned cycles
+        let data = "broadcaster -> a\n%a -> rx\n";
+        assert!(presses_until_low(data, "rx").is_err());
+    }
+
+    #[test]
+    fn inverters_feeding_each_other_are_rejected() {
+        let data = "broadcaster -> x\n%x -> x\n&a -> b, rx\n&b -> a\n";
+        assert_eq!(
+            presses_until_low(data, "rx"),
+            Err(String::from("The inverters before rx loop through a"))
+        );
+    }
+
+    #[test]
+    fn watchers_of_inverted_cycles() {
+        // Two counters of 2 and 3 bits, each lighting a conjunction once all its bits are on,
+        // inverted and joined before rx
+        let data = "broadcaster -> pa, pb\n%pa -> qa, ca\n%qa -> ca\n&ca -> ia\n&ia -> j\n%pb -> qb, cb\n%qb -> rb, cb\n%rb -> cb\n&cb -> ib\n&ib -> j\n&j -> rx\n";
+        let components = parse(data).unwrap();
+
+        let mut watchers = cycle_watchers(&components, "rx").unwrap();
+        watchers.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            watchers,
+            vec![
+                (String::from("ia"), Pulse::High),
+                (String::from("ib"), Pulse::High)
+            ]
+        );
+
+        // The counters never reset, so the first full count takes one press less than the next
+        assert_eq!(
+            presses_until_low(data, "rx"),
+            Err(String::from(
+                "ia does not send a High pulse periodically: Some(3) then Some(4) presses"
+            ))
+        );
+    }
+
+    #[test]
+    fn snapshot_after_presses() {
+        let components = parse(include_str!("data/day20/ex2")).unwrap();
+        let mut sim = PulseSim::new(components);
+        let expected = |states: &[(&str, &str)]| {
+            states
+                .iter()
+                .map(|(label, state)| (String::from(*label), String::from(*state)))
+                .collect::<HashMap<_, _>>()
+        };
+
+        sim.push_button(|_, _, _| {});
+        assert_eq!(
+            snapshot(&sim.components),
+            expected(&[
+                ("a", "On"),
+                ("b", "On"),
+                ("inv", "a:High"),
+                ("con", "a:High,b:High"),
+            ])
+        );
+
+        sim.push_button(|_, _, _| {});
+        assert_eq!(
+            snapshot(&sim.components),
+            expected(&[
+                ("a", "Off"),
+                ("b", "On"),
+                ("inv", "a:Low"),
+                ("con", "a:Low,b:High"),
+            ])
+        );
+    }
 }
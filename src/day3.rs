@@ -28,7 +28,7 @@ use std::collections::{
 enum Value {
     Number(u32),
     Symbol(char),
-    Dots(usize),
+    Dots(#[allow(dead_code)] usize),
 }
 
 impl Value {
@@ -73,7 +73,8 @@ fn parse(input: &str) -> IResult<&str, Vec<Cell>> {
                     let y = line_index as i64;
 
                     for (input, value) in raw_cells {
-                        let size = input.len();
+                        // Characters, not bytes, so that non-ASCII symbols take a single cell
+                        let size = input.chars().count();
                         cells.push(Cell { x, y, size, value });
                         x += size as i64;
                     }
@@ -121,11 +122,8 @@ fn first(
     println!("[{}] Sum of part numbers '{}'", name, sum)
 }
 
-fn second(
-    name: &str,
-    data: &str,
-) {
-    let (_, cells) = parse(data).finish().unwrap();
+// Coordinates and ratio of every `*` adjacent to exactly two numbers, in reading order
+fn gears(cells: &[Cell]) -> Vec<((i64, i64), u64)> {
     let mut gears = cells
         .iter()
         .filter_map(|cell| {
@@ -152,16 +150,27 @@ fn second(
         }
     });
 
-    let sum: u64 = gears
-        .values()
-        .filter_map(|numbers| {
+    let mut gears: Vec<_> = gears
+        .into_iter()
+        .filter_map(|(coords, numbers)| {
             if numbers.len() == 2 {
-                Some(numbers.iter().map(|x| *x as u64).product::<u64>())
+                Some((coords, numbers.iter().map(|x| *x as u64).product::<u64>()))
             } else {
                 None
             }
         })
-        .sum();
+        .collect();
+    gears.sort_by_key(|((x, y), _)| (*y, *x));
+
+    gears
+}
+
+fn second(
+    name: &str,
+    data: &str,
+) {
+    let (_, cells) = parse(data).finish().unwrap();
+    let sum: u64 = gears(&cells).iter().map(|(_, ratio)| ratio).sum();
 
     println!("[{}] Sum of part numbers '{}'", name, sum)
 }
@@ -172,3 +181,84 @@ pub fn run() {
     second("Second Example", include_str!("data/day3/ex1")); // 467835
     second("Second", include_str!("data/day3/input")); // 67779080
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all_dots_line_is_a_single_cell() {
+        let (_, cells) = parse("..........\n").finish().unwrap();
+
+        assert_eq!(cells.len(), 1);
+        assert!(matches!(cells[0].value, Value::Dots(10)));
+        assert_eq!((cells[0].x, cells[0].y, cells[0].size), (0, 0, 10));
+    }
+
+    #[test]
+    fn coordinates_align_across_lines() {
+        let (_, cells) = parse("12..*\n.....\n*.345\n..#..\n").finish().unwrap();
+        let positions: Vec<_> = cells
+            .iter()
+            .map(|cell| {
+                match cell.value {
+                    Value::Number(number) => (cell.x, cell.y, cell.size, Some(number), None),
+                    Value::Symbol(symbol) => (cell.x, cell.y, cell.size, None, Some(symbol)),
+                    Value::Dots(_) => (cell.x, cell.y, cell.size, None, None),
+                }
+            })
+            .collect();
+
+        assert_eq!(
+            positions,
+            vec![
+                (0, 0, 2, Some(12), None),
+                (2, 0, 2, None, None),
+                (4, 0, 1, None, Some('*')),
+                (0, 1, 5, None, None),
+                (0, 2, 1, None, Some('*')),
+                (1, 2, 1, None, None),
+                (2, 2, 3, Some(345), None),
+                (0, 3, 2, None, None),
+                (2, 3, 1, None, Some('#')),
+                (3, 3, 2, None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn unicode_symbols_take_a_single_cell() {
+        let (_, cells) = parse("€12.±\n...3.\n").finish().unwrap();
+        let positions: Vec<_> = cells
+            .iter()
+            .map(|cell| (cell.x, cell.y, cell.size))
+            .collect();
+
+        assert_eq!(
+            positions,
+            vec![
+                (0, 0, 1),
+                (1, 0, 2),
+                (3, 0, 1),
+                (4, 0, 1),
+                (0, 1, 3),
+                (3, 1, 1),
+                (4, 1, 1),
+            ]
+        );
+        assert!(matches!(cells[0].value, Value::Symbol('€')));
+        assert!(matches!(cells[3].value, Value::Symbol('±')));
+
+        // 12 touches €, 3 touches ±
+        assert!(cells[5].adjacent().contains(&(cells[3].x, cells[3].y)));
+        assert!(cells[1].adjacent().contains(&(cells[0].x, cells[0].y)));
+    }
+
+    #[test]
+    fn gears_of_example() {
+        let (_, cells) = parse(include_str!("data/day3/ex1")).finish().unwrap();
+
+        // The `*` next to 617 only is not a gear
+        assert_eq!(gears(&cells), vec![((3, 1), 467 * 35), ((5, 8), 755 * 598)]);
+    }
+}
@@ -25,6 +25,7 @@ use nom::{
 use std::collections::{
     HashMap,
     HashSet,
+    VecDeque,
 };
 
 #[derive(Debug)]
@@ -42,6 +43,21 @@ impl Card {
             .count()
     }
 
+    // One point for the first match, then doubled for each other match
+    fn score(&self) -> Result<u64, String> {
+        match self.matching_numbers_count() {
+            0 => Ok(0),
+            winning => {
+                u32::try_from(winning - 1)
+                    .ok()
+                    .and_then(|exponent| 2_u64.checked_pow(exponent))
+                    .ok_or_else(|| {
+                        format!("Card {} score overflows with {} matches", self.id, winning)
+                    })
+            }
+        }
+    }
+
     fn parse(input: &str) -> IResult<&str, Self> {
         map(
             tuple((
@@ -75,22 +91,19 @@ fn parse(input: &str) -> IResult<&str, Vec<Card>> {
     all_consuming(many0(terminated(Card::parse, opt(line_ending))))(input)
 }
 
+fn total_score(cards: &[Card]) -> Result<u64, String> {
+    cards.iter().try_fold(0_u64, |sum, card| {
+        sum.checked_add(card.score()?)
+            .ok_or_else(|| String::from("Total score overflows"))
+    })
+}
+
 fn first(
     name: &str,
     data: &str,
 ) {
     let (_, cards) = parse(data).finish().unwrap();
-    let sum: i32 = cards
-        .iter()
-        .map(|card| {
-            let winning = card.matching_numbers_count();
-            if winning == 0 {
-                0
-            } else {
-                2_i32.pow((winning - 1) as u32)
-            }
-        })
-        .sum();
+    let sum = total_score(&cards).unwrap();
 
     println!("[{}] Sum is '{}'", name, sum);
 }
@@ -122,9 +135,107 @@ fn second(
     println!("[{}] Card count is '{}'", name, sum);
 }
 
+// Same card count, processing the cards one at a time: a card only ever wins copies of the
+// next few cards, so only the copies pending for those are kept
+// Returns the card count and the largest number of pending copies kept at once
+fn card_count_streaming(cards: impl IntoIterator<Item = Card>) -> (usize, usize) {
+    let mut pending = VecDeque::<usize>::new();
+    let mut peak = 0;
+    let mut total = 0;
+
+    for card in cards {
+        let copies = 1 + pending.pop_front().unwrap_or_default();
+        total += copies;
+
+        for index in 0..card.matching_numbers_count() {
+            match pending.get_mut(index) {
+                Some(count) => *count += copies,
+                None => pending.push_back(copies),
+            }
+        }
+        peak = peak.max(pending.len());
+    }
+
+    (total, peak)
+}
+
+fn second_streaming(
+    name: &str,
+    data: &str,
+) {
+    let cards = data
+        .lines()
+        .map(|line| all_consuming(Card::parse)(line).finish().unwrap().1);
+    let (sum, peak) = card_count_streaming(cards);
+
+    println!(
+        "[{}] Card count is '{}', with at most {} pending cards",
+        name, sum, peak
+    );
+}
+
 pub fn run() {
     first("First example", include_str!("data/day4/ex1")); // 13
     first("First", include_str!("data/day4/input")); // 23441
     second("Second example", include_str!("data/day4/ex1")); // 30
     second("Second", include_str!("data/day4/input")); // 5923918
+    second_streaming("Second streaming", include_str!("data/day4/input")); // 5923918
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_right_aligned_columns() {
+        let data = "Card   1:  1 21  5 | 21  7  1 38\nCard  12: 41 48  3 |  3 86  6 48\n";
+        let (_, cards) = parse(data).finish().unwrap();
+
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].id, 1);
+        assert_eq!(cards[0].winning, HashSet::from([1, 21, 5]));
+        assert_eq!(cards[0].played, vec![21, 7, 1, 38]);
+        assert_eq!(cards[0].matching_numbers_count(), 2);
+        assert_eq!(cards[1].id, 12);
+        assert_eq!(cards[1].played, vec![3, 86, 6, 48]);
+        assert_eq!(cards[1].matching_numbers_count(), 2);
+    }
+
+    fn card_with_matches(matches: u16) -> Card {
+        Card {
+            id: 1,
+            winning: (1..=matches).collect(),
+            played: (1..=matches).collect(),
+        }
+    }
+
+    #[test]
+    fn score_of_many_matches() {
+        assert_eq!(card_with_matches(40).score(), Ok(1 << 39));
+        assert_eq!(
+            card_with_matches(70).score(),
+            Err(String::from("Card 1 score overflows with 70 matches"))
+        );
+        assert_eq!(
+            total_score(&[card_with_matches(64), card_with_matches(64)]),
+            Err(String::from("Total score overflows"))
+        );
+    }
+
+    #[test]
+    fn streaming_card_count() {
+        let (_, cards) = parse(include_str!("data/day4/ex1")).finish().unwrap();
+        // Card 1 has the most matches, 4
+        assert_eq!(card_count_streaming(cards), (30, 4));
+
+        let (_, cards) = parse(include_str!("data/day4/input")).finish().unwrap();
+        let max_matches = cards
+            .iter()
+            .map(|card| card.matching_numbers_count())
+            .max()
+            .unwrap_or_default();
+        let (total, peak) = card_count_streaming(cards);
+        assert_eq!(total, 5923918);
+        assert!(peak <= max_matches);
+    }
 }
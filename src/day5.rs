@@ -8,6 +8,7 @@ use nom::{
     combinator::{
         all_consuming,
         map,
+        map_res,
         opt,
     },
     multi::{
@@ -23,7 +24,13 @@ use nom::{
     IResult,
 };
 use rayon::prelude::*;
-use std::ops::Range;
+use std::{
+    ops::Range,
+    time::{
+        Duration,
+        Instant,
+    },
+};
 
 #[derive(Debug)]
 struct ConversionRange {
@@ -76,15 +83,27 @@ struct ConversionMap {
 }
 
 impl ConversionMap {
-    fn new(ranges: Vec<ConversionRange>) -> Self {
+    fn new(ranges: Vec<ConversionRange>) -> Result<Self, String> {
         let mut ranges = ranges;
-        ranges.sort_by(|a, b| a.source_range_start.cmp(&b.source_range_start));
+        ranges.sort_by_key(|range| range.source_range_start);
+
+        // Overlapping source ranges would make the mapping ambiguous
+        if let Some(window) = ranges
+            .windows(2)
+            .find(|window| window[0].source_range_end() > window[1].source_range_start)
+        {
+            return Err(format!(
+                "Overlapping source ranges {:?} and {:?}",
+                window[0].source_range_start..window[0].source_range_end(),
+                window[1].source_range_start..window[1].source_range_end(),
+            ));
+        }
 
-        Self { ranges }
+        Ok(Self { ranges })
     }
 
     fn parse(input: &str) -> IResult<&str, Self> {
-        map(
+        map_res(
             many0(terminated(ConversionRange::parse, opt(line_ending))),
             ConversionMap::new,
         )(input)
@@ -231,17 +250,36 @@ impl Almanac {
         all_consuming(almanac)(input)
     }
 
-    fn associate(
+    // Returns the seed, soil, fertilizer, water, light, temperature, humidity and location
+    fn trace(
         &self,
         seed: u64,
-    ) -> u64 {
+    ) -> [u64; 8] {
         let soil = self.seed_to_soil_map.associate(seed);
         let fertilizer = self.soil_to_fertilizer_map.associate(soil);
         let water = self.fertilizer_to_water_map.associate(fertilizer);
         let light = self.water_to_light_map.associate(water);
         let temperature = self.light_to_temperature_map.associate(light);
         let humidity = self.temperature_to_humidity_map.associate(temperature);
-        self.humidity_to_location_map.associate(humidity)
+        let location = self.humidity_to_location_map.associate(humidity);
+
+        [
+            seed,
+            soil,
+            fertilizer,
+            water,
+            light,
+            temperature,
+            humidity,
+            location,
+        ]
+    }
+
+    fn associate(
+        &self,
+        seed: u64,
+    ) -> u64 {
+        self.trace(seed)[7]
     }
 
     fn associate_ranges(
@@ -258,20 +296,65 @@ impl Almanac {
             .associate_ranges(temperature);
         self.humidity_to_location_map.associate_ranges(humidity)
     }
+
+    // Seeds read as start and length pairs, built as they are consumed
+    fn seed_ranges(&self) -> impl Iterator<Item = Range<u64>> + Send + '_ {
+        self.seeds.chunks_exact(2).map(|chunk| {
+            let start = chunk[0];
+            let size = chunk[1];
+            start..(start + size)
+        })
+    }
+
+    // Min location of the seed ranges, associating every single seed
+    fn min_location_brute_force(&self) -> u64 {
+        self.seed_ranges()
+            .par_bridge()
+            .map(|range| {
+                range
+                    .into_par_iter()
+                    .map(|seed| self.associate(seed))
+                    .min()
+                    .unwrap_or_default()
+            })
+            .min()
+            .unwrap_or_default()
+    }
+
+    // Min location of the seed ranges, associating whole ranges at once
+    fn min_location_of_ranges(&self) -> u64 {
+        // Min destination is the start of the first destination range
+        merge_ranges(self.associate_ranges(self.seed_ranges().collect()))
+            .first()
+            .map(|r| r.start)
+            .unwrap_or_default()
+    }
+}
+
+// Min location of the seeds, read either as individual values or as start and length pairs
+fn min_location(
+    data: &str,
+    ranges: bool,
+) -> u64 {
+    let (_, almanac) = Almanac::parse(data).finish().unwrap();
+
+    if ranges {
+        almanac.min_location_of_ranges()
+    } else {
+        almanac
+            .seeds
+            .iter()
+            .map(|seed| almanac.associate(*seed))
+            .min()
+            .unwrap_or_default()
+    }
 }
 
 pub fn first(
     name: &str,
     data: &str,
 ) {
-    let (_, almanac) = Almanac::parse(data).finish().unwrap();
-
-    let min_location = &almanac
-        .seeds
-        .iter()
-        .map(|seed| almanac.associate(*seed))
-        .min()
-        .unwrap_or_default();
+    let min_location = min_location(data, false);
 
     println!("[{}] Min location is {:?}", name, min_location);
 }
@@ -282,55 +365,81 @@ pub fn second(
 ) {
     let (_, almanac) = Almanac::parse(data).finish().unwrap();
 
-    let min_location = almanac
-        .seeds
-        .chunks_exact(2)
-        .collect::<Vec<_>>()
-        .par_iter()
-        .map(|chunk| {
-            let start = chunk[0];
-            let size = chunk[1];
-            let range = start..(start + size);
-
-            range
-                .into_par_iter()
-                .map(|seed| almanac.associate(seed))
-                .min()
-                .unwrap_or_default()
-        })
-        .min()
-        .unwrap_or_default();
+    let min_location = almanac.min_location_brute_force();
 
     println!("[{}] Min location is {:?}", name, min_location);
 }
 
+// Sorts the ranges and merges the ones overlapping or touching each other
+fn merge_ranges(mut ranges: Vec<Range<u64>>) -> Vec<Range<u64>> {
+    ranges.retain(|range| !range.is_empty());
+    ranges.sort_by_key(|range| range.start);
+
+    let mut merged: Vec<Range<u64>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+
+    merged
+}
+
+// Locations reachable from the seed ranges, sorted and merged
+fn location_ranges(data: &str) -> Vec<Range<u64>> {
+    let (_, almanac) = Almanac::parse(data).finish().unwrap();
+
+    // Map the ranges
+    merge_ranges(almanac.associate_ranges(almanac.seed_ranges().collect()))
+}
+
 pub fn second_v2(
     name: &str,
     data: &str,
 ) {
-    let (_, almanac) = Almanac::parse(data).finish().unwrap();
+    let min_location = min_location(data, true);
+    let locations: u64 = location_ranges(data).iter().map(|r| r.end - r.start).sum();
 
-    let ranges = almanac
-        .seeds
-        .chunks_exact(2)
-        .map(|chunk| {
-            let start = chunk[0];
-            let size = chunk[1];
-            start..(start + size)
-        })
-        .collect::<Vec<_>>();
+    println!(
+        "[{}] Min location is {:?} out of {} locations",
+        name, min_location, locations
+    );
+}
 
-    // Map the ranges
-    let destination_ranges = almanac.associate_ranges(ranges);
+// Way of finding the min location of the seed ranges
+type Strategy = fn(&Almanac) -> u64;
 
-    // Min destination is the min start of the destination ranges
-    let min_location = destination_ranges
-        .iter()
-        .map(|r| r.start)
-        .min()
-        .unwrap_or_default();
+// Runs every part two strategy on the same almanac, returning the answer of each and how long it
+// took to get it
+fn part_two_strategies(data: &str) -> Vec<(&'static str, u64, Duration)> {
+    let (_, almanac) = Almanac::parse(data).finish().unwrap();
 
-    println!("[{}] Min location is {:?}", name, min_location);
+    let strategies: [(&'static str, Strategy); 2] = [
+        ("brute force", Almanac::min_location_brute_force),
+        ("ranges", Almanac::min_location_of_ranges),
+    ];
+
+    strategies
+        .into_iter()
+        .map(|(strategy, min_location)| {
+            let start = Instant::now();
+            let answer = min_location(&almanac);
+            (strategy, answer, start.elapsed())
+        })
+        .collect()
+}
+
+pub fn compare(
+    name: &str,
+    data: &str,
+) {
+    for (strategy, min_location, duration) in part_two_strategies(data) {
+        println!(
+            "[{}] Min location is {:?} with {} in {:?}",
+            name, min_location, strategy, duration
+        );
+    }
 }
 
 pub fn run() {
@@ -339,5 +448,148 @@ pub fn run() {
     second("Second example", include_str!("data/day5/ex1")); // 46
     second("Second", include_str!("data/day5/input")); // 78775051
     second_v2("Second example V2", include_str!("data/day5/ex1")); // 46
-    second_v2("Second V2", include_str!("data/day5/input")); // 46
+    second_v2("Second V2", include_str!("data/day5/input")); // 78775051
+    compare("Compare example", include_str!("data/day5/ex1")); // 46
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlapping_ranges_are_rejected() {
+        let ranges = vec![
+            ConversionRange {
+                destination_range_start: 50,
+                source_range_start: 98,
+                range_length: 2,
+            },
+            ConversionRange {
+                destination_range_start: 52,
+                source_range_start: 90,
+                range_length: 10,
+            },
+        ];
+
+        assert!(ConversionMap::new(ranges).is_err());
+        assert!(ConversionMap::parse("50 98 2\n52 90 10\n").is_err());
+    }
+
+    #[test]
+    fn trace_first_seed_of_example() {
+        let (_, almanac) = Almanac::parse(include_str!("data/day5/ex1"))
+            .finish()
+            .unwrap();
+
+        assert_eq!(almanac.trace(79), [79, 81, 81, 81, 74, 78, 78, 82]);
+    }
+
+    // Maps 20..25 to 10..15 and the adjacent 25..30 to 100..105
+    fn adjacent_ranges_map() -> ConversionMap {
+        let (_, map) = ConversionMap::parse("10 20 5\n100 25 5\n")
+            .finish()
+            .unwrap();
+        map
+    }
+
+    #[test]
+    fn associate_range_outside_conversions() {
+        let map = adjacent_ranges_map();
+
+        assert_eq!(map.associate_range(0..10), vec![0..10]);
+        assert_eq!(map.associate_range(0..20), vec![0..20]);
+        assert_eq!(map.associate_range(30..50), vec![30..50]);
+    }
+
+    #[test]
+    fn associate_range_spanning_one_conversion() {
+        let map = adjacent_ranges_map();
+
+        assert_eq!(map.associate_range(20..25), vec![10..15]);
+        assert_eq!(map.associate_range(21..24), vec![11..14]);
+    }
+
+    #[test]
+    fn associate_range_straddling_adjacent_conversions() {
+        let map = adjacent_ranges_map();
+
+        assert_eq!(map.associate_range(23..27), vec![13..15, 100..102]);
+        assert_eq!(
+            map.associate_range(18..32),
+            vec![18..20, 10..15, 100..105, 30..32]
+        );
+    }
+
+    #[test]
+    fn merge_overlapping_and_touching_ranges() {
+        assert_eq!(
+            merge_ranges(vec![10..15, 0..5, 3..8, 8..9, 20..20, 12..14]),
+            vec![0..9, 10..15]
+        );
+    }
+
+    #[test]
+    fn location_ranges_of_example() {
+        let ranges = location_ranges(include_str!("data/day5/ex1"));
+
+        assert_eq!(ranges.first().map(|range| range.start), Some(46));
+        assert!(ranges
+            .windows(2)
+            .all(|window| window[0].end < window[1].start));
+        assert_eq!(
+            ranges
+                .iter()
+                .map(|range| range.end - range.start)
+                .sum::<u64>(),
+            27
+        );
+    }
+
+    #[test]
+    fn min_location_of_seeds_and_ranges() {
+        let example = include_str!("data/day5/ex1");
+        assert_eq!(min_location(example, false), 35);
+        assert_eq!(min_location(example, true), 46);
+
+        let input = include_str!("data/day5/input");
+        assert_eq!(min_location(input, false), 227653707);
+        assert_eq!(min_location(input, true), 78775051);
+    }
+
+    #[test]
+    fn associate_empty_range() {
+        let map = adjacent_ranges_map();
+
+        assert!(map.associate_range(22..22).is_empty());
+        assert!(map.associate_range(40..40).is_empty());
+    }
+
+    #[test]
+    fn part_two_strategies_agree() {
+        let results = part_two_strategies(include_str!("data/day5/ex1"));
+
+        let answers: Vec<_> = results
+            .iter()
+            .map(|(strategy, min_location, _)| (*strategy, *min_location))
+            .collect();
+        assert_eq!(answers, vec![("brute force", 46), ("ranges", 46)]);
+    }
+
+    #[test]
+    fn associate_and_associate_ranges_agree() {
+        let (_, almanac) = Almanac::parse(include_str!("data/day5/ex1"))
+            .finish()
+            .unwrap();
+
+        // Every source boundary of the example is below 100
+        for seed in 0..120 {
+            let location = almanac.associate(seed);
+            assert_eq!(
+                almanac.associate_ranges(std::iter::once(seed..(seed + 1)).collect()),
+                std::iter::once(location..(location + 1)).collect::<Vec<_>>(),
+                "seed {}",
+                seed
+            );
+        }
+    }
 }
@@ -8,6 +8,7 @@ use nom::{
     combinator::{
         all_consuming,
         map,
+        map_res,
     },
     multi::separated_list1,
     sequence::{
@@ -17,20 +18,57 @@ use nom::{
     Finish,
     IResult,
 };
+use std::num::ParseIntError;
 
 #[derive(Debug)]
 struct Race {
-    time: u64,
-    distance: u64,
+    time: u128,
+    distance: u128,
 }
 
 impl Race {
-    fn records(&self) -> Vec<u64> {
+    // The longest distance, reached when holding for half of the race, must be computable
+    fn new(
+        time: u128,
+        distance: u128,
+    ) -> Result<Self, String> {
+        let half = time / 2;
+        if (time - half).checked_mul(half).is_none() {
+            return Err(format!("Race of {} ms is too long", time));
+        }
+
+        Ok(Race { time, distance })
+    }
+
+    fn records(&self) -> Vec<u128> {
         (1..self.time)
             .map(|time| (self.time - time) * time)
             .filter(|distance| *distance > self.distance)
             .collect()
     }
+
+    // Same as the number of records, without going through every holding time
+    fn record_count(&self) -> u128 {
+        // The distance grows with the holding time up to half of the race, so the shortest
+        // winning hold can be searched for, the longest one being symmetric
+        let beats = |hold: u128| (self.time - hold) * hold > self.distance;
+        let half = self.time / 2;
+        if !beats(half) {
+            return 0;
+        }
+
+        let (mut low, mut high) = (0, half);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if beats(mid) {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+
+        self.time - 2 * low + 1
+    }
 }
 
 fn parse(input: &str) -> IResult<&str, Vec<Race>> {
@@ -38,12 +76,12 @@ fn parse(input: &str) -> IResult<&str, Vec<Race>> {
         tuple((
             delimited(
                 tuple((tag("Time:"), space1)),
-                separated_list1(space1, character::complete::u64),
+                separated_list1(space1, character::complete::u128),
                 line_ending,
             ),
             delimited(
                 tuple((tag("Distance:"), space1)),
-                separated_list1(space1, character::complete::u64),
+                separated_list1(space1, character::complete::u128),
                 line_ending,
             ),
         )),
@@ -58,7 +96,7 @@ fn parse(input: &str) -> IResult<&str, Vec<Race>> {
 }
 
 fn parse2(input: &str) -> IResult<&str, Race> {
-    all_consuming(map(
+    all_consuming(map_res(
         tuple((
             delimited(
                 tuple((tag("Time:"), space1)),
@@ -72,23 +110,35 @@ fn parse2(input: &str) -> IResult<&str, Race> {
             ),
         )),
         |(times, distances)| {
-            let time: u64 = times.join("").as_str().parse().unwrap();
-            let distance: u64 = distances.join("").as_str().parse().unwrap();
-            Race { time, distance }
+            // Too many digits overflow and fail the parsing
+            let time = times
+                .join("")
+                .parse()
+                .map_err(|e: ParseIntError| e.to_string())?;
+            let distance = distances
+                .join("")
+                .parse()
+                .map_err(|e: ParseIntError| e.to_string())?;
+            Race::new(time, distance)
         },
     ))(input)
 }
 
-fn first(
-    name: &str,
-    data: &str,
-) {
+// Number of ways to beat the record, for each race
+fn race_counts(data: &str) -> Vec<u64> {
     let (_, races) = parse(data).finish().unwrap();
 
-    let records: u64 = races
+    races
         .iter()
         .map(|race| race.records().len() as u64)
-        .product();
+        .collect()
+}
+
+fn first(
+    name: &str,
+    data: &str,
+) {
+    let records: u64 = race_counts(data).into_iter().product();
 
     println!("[{}] {:?}", name, records);
 }
@@ -98,7 +148,7 @@ fn second(
     data: &str,
 ) {
     let (_, race) = parse2(data).finish().unwrap();
-    let records = race.records().len();
+    let records = race.record_count();
     println!("[{}] {:?}", name, records);
 }
 
@@ -108,3 +158,52 @@ pub fn run() {
     second("Second example", include_str!("data/day6/ex1")); // 71503
     second("Second", include_str!("data/day6/input")); // 41513103
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn race_counts_of_example() {
+        assert_eq!(race_counts(include_str!("data/day6/ex1")), vec![4, 8, 9]);
+    }
+
+    #[test]
+    fn kerned_race_beyond_u64() {
+        // 2^64 ms, any holding time but the extremes beats a record of 0
+        let (_, race) = parse2("Time:      1844674407 3709551616\nDistance:  0\n")
+            .finish()
+            .unwrap();
+        assert_eq!(race.record_count(), u64::MAX as u128);
+
+        let data = "Time:      1844674407 3709551616 1844674407 3709551616\nDistance:  0\n";
+        assert!(parse2(data).is_err());
+    }
+
+    #[test]
+    fn race_too_long_to_compute_is_rejected() {
+        // Fits in a u128, but (time / 2)^2 does not
+        let data = "Time:      10000000000 0000000000\nDistance:  0\n";
+        assert!(parse2(data).is_err());
+        assert_eq!(
+            Race::new(100000000000000000000, 0).err(),
+            Some(String::from("Race of 100000000000000000000 ms is too long"))
+        );
+
+        // The longest race whose distances can all be computed
+        let time = 2 * (u64::MAX as u128) + 1;
+        assert_eq!(Race::new(time, 0).unwrap().record_count(), time - 1);
+        assert!(Race::new(time + 1, 0).is_err());
+    }
+
+    #[test]
+    fn record_count_matches_records() {
+        let (_, races) = parse(include_str!("data/day6/ex1")).finish().unwrap();
+        for race in races {
+            assert_eq!(race.record_count(), race.records().len() as u128);
+        }
+
+        let (_, race) = parse2(include_str!("data/day6/ex1")).finish().unwrap();
+        assert_eq!(race.record_count(), 71503);
+    }
+}
@@ -7,6 +7,7 @@ use nom::{
     combinator::{
         all_consuming,
         map,
+        map_res,
         opt,
     },
     multi::many1,
@@ -17,16 +18,17 @@ use nom::{
     Finish,
     IResult,
 };
-use phf::{
-    map::Map,
-    phf_map,
-};
 use std::{
     cmp::Ordering,
     collections::HashMap,
+    fmt::{
+        Display,
+        Formatter,
+    },
 };
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 enum HandType {
     FiveOfAKind,
     FourOfAKind,
@@ -51,6 +53,23 @@ impl HandType {
     }
 }
 
+impl Display for HandType {
+    fn fmt(
+        &self,
+        f: &mut Formatter<'_>,
+    ) -> std::fmt::Result {
+        f.write_str(match self {
+            HandType::FiveOfAKind => "Five of a Kind",
+            HandType::FourOfAKind => "Four of a Kind",
+            HandType::FullHouse => "Full House",
+            HandType::ThreeOfAKind => "Three of a Kind",
+            HandType::TwoPair => "Two Pair",
+            HandType::OnePair => "One Pair",
+            HandType::HighCard => "High Card",
+        })
+    }
+}
+
 impl Ord for HandType {
     fn cmp(
         &self,
@@ -70,51 +89,21 @@ impl PartialOrd for HandType {
 }
 
 #[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
-enum Card {
-    As,
-    King,
-    Queen,
-    Jack,
-    Ten,
-    Nine,
-    Eight,
-    Seven,
-    Six,
-    Five,
-    Four,
-    Three,
-    Two,
-    Joker,
+struct Card {
+    symbol: char,
+    // The higher, the stronger
+    strength: u8,
+    // Jokers count as the most common other card of their hand
+    joker: bool,
 }
 
 impl Card {
-    fn parse<'a>(
-        char_to_card: &'a Map<char, Card>
-    ) -> impl FnMut(&'a str) -> IResult<&'a str, Card> {
+    fn parse<'a>(card_order: &'a CardOrder) -> impl FnMut(&'a str) -> IResult<&'a str, Card> {
         map(
-            character::complete::satisfy(|c| char_to_card.contains_key(&c)),
-            |c| char_to_card[&c],
+            character::complete::satisfy(|c| card_order.cards.contains_key(&c)),
+            |c| card_order.cards[&c],
         )
     }
-
-    fn order(&self) -> u8 {
-        match self {
-            Card::As => 13,
-            Card::King => 12,
-            Card::Queen => 11,
-            Card::Jack => 10,
-            Card::Ten => 9,
-            Card::Nine => 8,
-            Card::Eight => 7,
-            Card::Seven => 6,
-            Card::Six => 5,
-            Card::Five => 4,
-            Card::Four => 3,
-            Card::Three => 2,
-            Card::Two => 1,
-            Card::Joker => 0,
-        }
-    }
 }
 
 impl Ord for Card {
@@ -122,7 +111,7 @@ impl Ord for Card {
         &self,
         other: &Self,
     ) -> Ordering {
-        self.order().cmp(&other.order())
+        self.strength.cmp(&other.strength)
     }
 }
 
@@ -139,22 +128,166 @@ impl PartialOrd for Card {
 struct Hand {
     cards: Vec<Card>,
     bid: u64,
+    // Position of the hand in the input
+    index: usize,
+}
+
+// Cards are rendered through `Display`, as in the input, e.g. `32T3K`
+#[cfg(feature = "serde")]
+impl serde::Serialize for Hand {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut hand = serializer.serialize_struct("Hand", 3)?;
+        hand.serialize_field("cards", &self.to_string())?;
+        hand.serialize_field("bid", &self.bid)?;
+        hand.serialize_field("index", &self.index)?;
+        hand.end()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum TieBreak {
+    // Compare the cards one by one, as in the puzzle
+    CardOrder,
+    // The hand appearing first in the input ranks lower
+    InputOrder,
+}
+
+// Strength of each card from an ordering like `AKQT98765432J`, the first card being the strongest
+fn card_order_from_str(order: &str) -> Result<HashMap<char, u8>, String> {
+    // Strengths start at 1 and must fit in a u8
+    let count = order.chars().count();
+    if count == 0 || count > u8::MAX as usize {
+        return Err(format!(
+            "Card order {} has {} cards, expected 1 to {}",
+            order,
+            count,
+            u8::MAX
+        ));
+    }
+
+    let mut strengths = HashMap::new();
+    for (index, card) in order.chars().enumerate() {
+        if strengths.insert(card, (count - index) as u8).is_some() {
+            return Err(format!(
+                "Card {} appears twice in the card order {}",
+                card, order
+            ));
+        }
+    }
+
+    Ok(strengths)
+}
+
+// The cards of a game and their strengths, read from the strongest to the weakest
+#[derive(Debug)]
+struct CardOrder {
+    cards: HashMap<char, Card>,
+}
+
+impl CardOrder {
+    fn new(
+        order: &str,
+        joker: Option<char>,
+    ) -> Result<Self, String> {
+        let strengths = card_order_from_str(order)?;
+        if let Some(joker) = joker.filter(|joker| !strengths.contains_key(joker)) {
+            return Err(format!(
+                "Joker {} is not in the card order {}",
+                joker, order
+            ));
+        }
+
+        Ok(CardOrder {
+            cards: strengths
+                .into_iter()
+                .map(|(symbol, strength)| {
+                    let card = Card {
+                        symbol,
+                        strength,
+                        joker: joker == Some(symbol),
+                    };
+                    (symbol, card)
+                })
+                .collect(),
+        })
+    }
 }
 
 impl Hand {
-    fn parse<'a>(
-        char_to_card: &'a Map<char, Card>
-    ) -> impl FnMut(&'a str) -> IResult<&'a str, Self> {
-        map(
+    // Hand types are told apart by counting the distinct cards, which only works with 5 cards
+    fn new(
+        cards: Vec<Card>,
+        bid: u64,
+    ) -> Result<Self, String> {
+        if cards.len() != 5 {
+            return Err(format!(
+                "Hand {} has {} cards, expected 5",
+                cards.iter().map(|card| card.symbol).collect::<String>(),
+                cards.len()
+            ));
+        }
+
+        Ok(Hand {
+            cards,
+            bid,
+            index: 0,
+        })
+    }
+
+    fn parse<'a>(card_order: &'a CardOrder) -> impl FnMut(&'a str) -> IResult<&'a str, Self> {
+        map_res(
             separated_pair(
-                many1(Card::parse(char_to_card)),
+                many1(Card::parse(card_order)),
                 space1,
                 character::complete::u64,
             ),
-            |(cards, bid)| Hand { cards, bid },
+            |(cards, bid)| Hand::new(cards, bid),
         )
     }
 
+    // Builds a hand straight from its cards, with the same checks as the parsing
+    #[cfg(test)]
+    fn from_str(
+        cards: &str,
+        bid: u64,
+        card_order: &CardOrder,
+    ) -> Result<Hand, String> {
+        let cards = cards
+            .chars()
+            .map(|c| {
+                card_order
+                    .cards
+                    .get(&c)
+                    .copied()
+                    .ok_or_else(|| format!("Card {} is not in the card order", c))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Hand::new(cards, bid)
+    }
+
+    fn compare(
+        &self,
+        other: &Self,
+        tie_break: TieBreak,
+    ) -> Ordering {
+        let hand_type_ord = self.hand_type().cmp(&other.hand_type());
+        match hand_type_ord {
+            Ordering::Equal => {
+                match tie_break {
+                    TieBreak::CardOrder => self.cards.cmp(&other.cards),
+                    TieBreak::InputOrder => self.index.cmp(&other.index),
+                }
+            }
+            _ => hand_type_ord,
+        }
+    }
+
     fn hand_type(&self) -> HandType {
         let mut card_counts =
             self.cards
@@ -170,7 +303,8 @@ impl Hand {
 
         // Distribute the joker to the biggest group, if we don't have only jokers
         if card_counts.len() > 1 {
-            if let Some(jokers) = card_counts.remove(&Card::Joker) {
+            let joker = card_counts.keys().find(|card| card.joker).copied();
+            if let Some(jokers) = joker.and_then(|joker| card_counts.remove(&joker)) {
                 if let Some((card, _)) = card_counts.iter().max_by_key(|(_, count)| **count) {
                     card_counts
                         .entry(*card)
@@ -201,16 +335,26 @@ impl Hand {
     }
 }
 
+impl Display for Hand {
+    fn fmt(
+        &self,
+        f: &mut Formatter<'_>,
+    ) -> std::fmt::Result {
+        for card in &self.cards {
+            write!(f, "{}", card.symbol)?;
+        }
+
+        Ok(())
+    }
+}
+
 impl Ord for Hand {
     fn cmp(
         &self,
         other: &Self,
     ) -> Ordering {
-        let hand_type_ord = self.hand_type().cmp(&other.hand_type());
-        match hand_type_ord {
-            Ordering::Equal => self.cards.cmp(&other.cards),
-            _ => hand_type_ord,
-        }
+        self.compare(other, TieBreak::CardOrder)
+            .then_with(|| self.index.cmp(&other.index))
     }
 }
 
@@ -223,54 +367,77 @@ impl PartialOrd for Hand {
     }
 }
 
-const CHAR_TO_CARD: Map<char, Card> = phf_map! {
-    'A' => Card::As,
-    'K' => Card::King,
-    'Q' => Card::Queen,
-    'J' => Card::Jack,
-    'T' => Card::Ten,
-    '9' => Card::Nine,
-    '8' => Card::Eight,
-    '7' => Card::Seven,
-    '6' => Card::Six,
-    '5' => Card::Five,
-    '4' => Card::Four,
-    '3' => Card::Three,
-    '2' => Card::Two
-};
-
-const CHAR_TO_CARD_2: Map<char, Card> = phf_map! {
-    'A' => Card::As,
-    'K' => Card::King,
-    'Q' => Card::Queen,
-    'J' => Card::Joker,
-    'T' => Card::Ten,
-    '9' => Card::Nine,
-    '8' => Card::Eight,
-    '7' => Card::Seven,
-    '6' => Card::Six,
-    '5' => Card::Five,
-    '4' => Card::Four,
-    '3' => Card::Three,
-    '2' => Card::Two
-};
+// Card orders of the puzzle, the second one turning jacks into jokers
+const CARD_ORDER: &str = "AKQJT98765432";
+const CARD_ORDER_2: &str = "AKQT98765432J";
+const JOKER: char = 'J';
 
 fn parse_hands<'a>(
-    char_to_card: &'a Map<char, Card>
+    card_order: &'a CardOrder
 ) -> impl FnMut(&'a str) -> IResult<&'a str, Vec<Hand>> {
-    all_consuming(many1(terminated(
-        Hand::parse(char_to_card),
-        opt(line_ending),
-    )))
+    all_consuming(map(
+        many1(terminated(Hand::parse(card_order), opt(line_ending))),
+        |hands| {
+            hands
+                .into_iter()
+                .enumerate()
+                .map(|(index, hand)| Hand { index, ..hand })
+                .collect()
+        },
+    ))
+}
+
+// Hands from the weakest to the strongest
+fn ranked_hands(
+    data: &str,
+    card_order: &CardOrder,
+    tie_break: TieBreak,
+) -> Vec<Hand> {
+    let (_, mut hands) = parse_hands(card_order)(data).finish().unwrap();
+    hands.sort_by(|a, b| a.compare(b, tie_break));
+    hands
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct RankedHand<'a> {
+    rank: u64,
+    #[serde(flatten)]
+    hand: &'a Hand,
+    hand_type: HandType,
+    winnings: u64,
+}
+
+// Ranked hands along with their type and winnings, as a JSON array
+#[cfg(feature = "serde")]
+fn ranked_json(
+    data: &str,
+    card_order: &CardOrder,
+) -> String {
+    let hands = ranked_hands(data, card_order, TieBreak::CardOrder);
+    let ranked: Vec<_> = hands
+        .iter()
+        .zip(1_u64..)
+        .map(|(hand, rank)| {
+            RankedHand {
+                rank,
+                hand,
+                hand_type: hand.hand_type(),
+                winnings: rank * hand.bid,
+            }
+        })
+        .collect();
+
+    serde_json::to_string(&ranked).unwrap()
 }
 
 fn total_winnings(
     name: &str,
     data: &str,
-    char_to_card: &Map<char, Card>,
+    card_order: &CardOrder,
+    tie_break: TieBreak,
 ) {
-    let (_, mut hands) = parse_hands(char_to_card)(data).finish().unwrap();
-    hands.sort();
+    let hands = ranked_hands(data, card_order, tie_break);
 
     let total: u64 = hands
         .iter()
@@ -285,14 +452,37 @@ fn first(
     name: &str,
     data: &str,
 ) {
-    total_winnings(name, data, &CHAR_TO_CARD)
+    let card_order = CardOrder::new(CARD_ORDER, None).unwrap();
+    total_winnings(name, data, &card_order, TieBreak::CardOrder)
 }
 
 fn second(
     name: &str,
     data: &str,
 ) {
-    total_winnings(name, data, &CHAR_TO_CARD_2)
+    let card_order = CardOrder::new(CARD_ORDER_2, Some(JOKER)).unwrap();
+    total_winnings(name, data, &card_order, TieBreak::CardOrder)
+}
+
+// Plays with any card order, optionally with a joker
+fn custom_order(
+    name: &str,
+    data: &str,
+    order: &str,
+    joker: Option<char>,
+) {
+    match CardOrder::new(order, joker) {
+        Ok(card_order) => total_winnings(name, data, &card_order, TieBreak::CardOrder),
+        Err(error) => println!("[{}] {}", name, error),
+    }
+}
+
+fn first_input_order(
+    name: &str,
+    data: &str,
+) {
+    let card_order = CardOrder::new(CARD_ORDER, None).unwrap();
+    total_winnings(name, data, &card_order, TieBreak::InputOrder)
 }
 
 pub fn run() {
@@ -300,4 +490,177 @@ pub fn run() {
     first("First", include_str!("data/day7/input")); // 248569531
     second("Second example", include_str!("data/day7/ex1")); // 5905
     second("Second", include_str!("data/day7/input")); // 250382098
+    custom_order(
+        "Example by reversed order",
+        include_str!("data/day7/ex1"),
+        "23456789TJQKA",
+        None,
+    ); // 6833
+    first_input_order(
+        "First example by input order",
+        include_str!("data/day7/ex1"),
+    );
+    #[cfg(feature = "serde")]
+    println!(
+        "[First example JSON] {}",
+        ranked_json(
+            include_str!("data/day7/ex1"),
+            &CardOrder::new(CARD_ORDER, None).unwrap()
+        )
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card_order() -> CardOrder {
+        CardOrder::new(CARD_ORDER, None).unwrap()
+    }
+
+    fn card_order_2() -> CardOrder {
+        CardOrder::new(CARD_ORDER_2, Some(JOKER)).unwrap()
+    }
+
+    #[test]
+    fn tie_break_identical_hands() {
+        let card_order = card_order();
+        let (_, hands) = parse_hands(&card_order)("T55J5 10\nT55J5 20\n")
+            .finish()
+            .unwrap();
+
+        assert_eq!(
+            hands[0].compare(&hands[1], TieBreak::CardOrder),
+            Ordering::Equal
+        );
+        assert_eq!(
+            hands[0].compare(&hands[1], TieBreak::InputOrder),
+            Ordering::Less
+        );
+        assert_eq!(
+            hands[1].compare(&hands[0], TieBreak::InputOrder),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn display_hands_and_types() {
+        let (card_order, card_order_2) = (card_order(), card_order_2());
+        let hand = Hand::from_str("T55J5", 684, &card_order).unwrap();
+        assert_eq!(hand.to_string(), "T55J5");
+        assert_eq!(hand.hand_type().to_string(), "Three of a Kind");
+
+        let hand = Hand::from_str("T55J5", 684, &card_order_2).unwrap();
+        assert_eq!(hand.to_string(), "T55J5");
+        assert_eq!(hand.hand_type().to_string(), "Four of a Kind");
+
+        assert_eq!(HandType::FiveOfAKind.to_string(), "Five of a Kind");
+        assert_eq!(HandType::FullHouse.to_string(), "Full House");
+        assert_eq!(HandType::TwoPair.to_string(), "Two Pair");
+        assert_eq!(HandType::OnePair.to_string(), "One Pair");
+        assert_eq!(HandType::HighCard.to_string(), "High Card");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn ranked_json_of_example() {
+        let json = ranked_json(include_str!("data/day7/ex1"), &card_order());
+
+        assert!(json.starts_with(
+            r#"[{"rank":1,"cards":"32T3K","bid":765,"index":0,"hand_type":"OnePair","winnings":765},"#
+        ));
+        assert!(json.ends_with(
+            r#"{"rank":5,"cards":"QQQJA","bid":483,"index":4,"hand_type":"ThreeOfAKind","winnings":2415}]"#
+        ));
+    }
+
+    #[test]
+    fn custom_card_orders() {
+        let data = include_str!("data/day7/ex1");
+        let winnings = |data, order, joker| {
+            let card_order = CardOrder::new(order, joker).unwrap();
+            ranked_hands(data, &card_order, TieBreak::CardOrder)
+                .iter()
+                .zip(1..)
+                .map(|(hand, rank)| rank * hand.bid)
+                .sum::<u64>()
+        };
+
+        // The presets
+        assert_eq!(winnings(data, CARD_ORDER, None), 6440);
+        assert_eq!(winnings(data, CARD_ORDER_2, Some(JOKER)), 5905);
+
+        // Reversed, KTJJT beats KK677 and T55J5 beats QQQJA
+        assert_eq!(
+            winnings(data, "23456789TJQKA", None),
+            765 + 28 * 2 + 220 * 3 + 483 * 4 + 684 * 5
+        );
+
+        // Cards unknown to the presets, with the same ranks as the first part
+        let renamed = data.replace('T', "Z").replace('K', "Y");
+        assert_eq!(winnings(&renamed, "AYQJZ98765432", None), 6440);
+
+        // Fives as jokers make T55J5 a four of a kind, ranked above QQQJA
+        assert_eq!(
+            winnings(data, "AKQJT98765432", Some('5')),
+            765 + 220 * 2 + 28 * 3 + 483 * 4 + 684 * 5
+        );
+    }
+
+    #[test]
+    fn invalid_card_orders_are_rejected() {
+        assert_eq!(
+            card_order_from_str("AK2"),
+            Ok(HashMap::from([('A', 3), ('K', 2), ('2', 1)]))
+        );
+        assert_eq!(
+            card_order_from_str("AKA").err(),
+            Some(String::from("Card A appears twice in the card order AKA"))
+        );
+        assert_eq!(
+            card_order_from_str("").err(),
+            Some(String::from("Card order  has 0 cards, expected 1 to 255"))
+        );
+        assert!(card_order_from_str(&('\u{100}'..'\u{1ff}').collect::<String>()).is_ok());
+        assert!(card_order_from_str(&('\u{100}'..'\u{200}').collect::<String>()).is_err());
+        assert_eq!(
+            CardOrder::new("AK", Some('J')).err(),
+            Some(String::from("Joker J is not in the card order AK"))
+        );
+
+        // Cards missing from the order do not parse
+        let card_order = CardOrder::new("AK", None).unwrap();
+        assert!(parse_hands(&card_order)("AKAKQ 1\n").is_err());
+    }
+
+    #[test]
+    fn hands_of_the_wrong_length_are_rejected() {
+        let card_order = card_order();
+        assert!(parse_hands(&card_order)("32T3 765\n").is_err());
+        assert!(parse_hands(&card_order)("32T3KK 765\n").is_err());
+
+        assert_eq!(
+            Hand::from_str("32T3", 765, &card_order).err(),
+            Some(String::from("Hand 32T3 has 4 cards, expected 5"))
+        );
+        assert!(Hand::from_str("32T3KK", 765, &card_order).is_err());
+        assert_eq!(
+            Hand::from_str("32X3K", 765, &card_order).err(),
+            Some(String::from("Card X is not in the card order"))
+        );
+    }
+
+    #[test]
+    fn hand_types_from_str() {
+        let hand_type =
+            |cards, card_order| Hand::from_str(cards, 0, card_order).unwrap().hand_type();
+        let (card_order, card_order_2) = (card_order(), card_order_2());
+
+        assert_eq!(hand_type("32T3K", &card_order), HandType::OnePair);
+        assert_eq!(hand_type("KK677", &card_order), HandType::TwoPair);
+        assert_eq!(hand_type("T55J5", &card_order), HandType::ThreeOfAKind);
+        assert_eq!(hand_type("T55J5", &card_order_2), HandType::FourOfAKind);
+        assert_eq!(hand_type("JJJJJ", &card_order_2), HandType::FiveOfAKind);
+        assert_eq!(hand_type("23456", &card_order), HandType::HighCard);
+    }
 }
@@ -23,7 +23,17 @@ use nom::{
     Finish,
     IResult,
 };
-use std::collections::HashMap;
+use num::integer::{
+    ExtendedGcd,
+    Integer,
+};
+use std::{
+    collections::HashMap,
+    fmt::{
+        Display,
+        Formatter,
+    },
+};
 
 #[derive(Clone, Copy, Debug)]
 enum Direction {
@@ -68,6 +78,74 @@ impl Node<'_> {
     }
 }
 
+// Path of a ghost, which ends up looping as there are finitely many nodes and directions
+#[derive(Debug)]
+struct GhostCycle {
+    // Steps at which an end node is reached before entering the loop
+    transient_ends: Vec<usize>,
+    // First step of the loop, and how many steps it takes
+    start: usize,
+    length: usize,
+    // Steps at which an end node is reached during the first run of the loop
+    // If the loop starts at step 0, its start is only reached again at its end, so it is
+    // recorded there
+    ends: Vec<usize>,
+}
+
+impl GhostCycle {
+    fn at_end(
+        &self,
+        step: usize,
+    ) -> bool {
+        if step < self.start {
+            self.transient_ends.contains(&step)
+        } else {
+            let position = self.start + (step - self.start) % self.length;
+            self.ends.contains(&position) || self.ends.contains(&(position + self.length))
+        }
+    }
+}
+
+// First step at which every ghost is at an end node, each ghost `(offset, period)` being at an end
+// node at `offset`, then every `period` steps
+fn synchronize(cycles: &[(usize, usize)]) -> Option<usize> {
+    let latest = cycles.iter().map(|(offset, _)| *offset).max()?;
+
+    // Every ghost loops from its start: the LCM of the periods is enough
+    if cycles.iter().all(|(offset, period)| offset % period == 0) {
+        let lcm = cycles
+            .iter()
+            .fold(1, |lcm, (_, period)| num::integer::lcm(lcm, *period));
+        return Some(latest.div_ceil(lcm).max(1) * lcm);
+    }
+
+    // Chinese remainder theorem, merging the congruences `step = offset (mod period)` one by one,
+    // the periods not being necessarily coprime
+    let (residue, modulus) =
+        cycles
+            .iter()
+            .try_fold((0_i128, 1_i128), |(residue, modulus), (offset, period)| {
+                let period = *period as i128;
+                let offset = *offset as i128 % period;
+                let ExtendedGcd { gcd, x, .. } = modulus.extended_gcd(&period);
+                if (offset - residue) % gcd != 0 {
+                    return None;
+                }
+
+                let lcm = modulus / gcd * period;
+                let k = ((offset - residue) / gcd * x).rem_euclid(period / gcd);
+                Some(((residue + modulus * k).rem_euclid(lcm), lcm))
+            })?;
+
+    // Each ghost must have reached its first end node
+    let (residue, modulus) = (residue as usize, modulus as usize);
+    if residue >= latest {
+        Some(residue)
+    } else {
+        Some(residue + (latest - residue).div_ceil(modulus) * modulus)
+    }
+}
+
 #[derive(Debug)]
 struct Network<'a> {
     directions: Vec<Direction>,
@@ -95,14 +173,14 @@ impl Network<'_> {
     fn node_for_label(
         &self,
         label: &str,
-    ) -> &Node {
+    ) -> &Node<'_> {
         self.nodes.get(label).unwrap()
     }
 
     fn follow_until<'a>(
         &'a self,
         start: &'a Node,
-        stop: fn(&Node) -> bool,
+        stop: impl Fn(&Node) -> bool,
     ) -> Vec<&'a str> {
         let mut node = start;
         let mut visited = Vec::new();
@@ -121,6 +199,136 @@ impl Network<'_> {
 
         visited
     }
+
+    // Follows the path until its state (node and position in the directions) repeats
+    fn ghost_cycle(
+        &self,
+        start: &Node,
+        end_suffix: char,
+    ) -> GhostCycle {
+        let mut node = start;
+        let mut seen = HashMap::new();
+        let mut ends = Vec::new();
+        for (step, (index, direction)) in self.directions.iter().enumerate().cycle().enumerate() {
+            if let Some(loop_start) = seen.insert((node.label, index), step) {
+                // Step 0 is never recorded as ghosts have to move, but its state is back here
+                if loop_start == 0 && node.label.ends_with(end_suffix) {
+                    ends.push(step);
+                }
+
+                let (transient_ends, ends) = ends.into_iter().partition(|end| *end < loop_start);
+                return GhostCycle {
+                    transient_ends,
+                    start: loop_start,
+                    length: step - loop_start,
+                    ends,
+                };
+            }
+
+            if step > 0 && node.label.ends_with(end_suffix) {
+                ends.push(step);
+            }
+
+            match direction {
+                Direction::Left => node = self.node_for_label(node.left),
+                Direction::Right => node = self.node_for_label(node.right),
+            }
+        }
+
+        unreachable!("The directions are never empty")
+    }
+
+    // Steps at which an end node is reached, until the path state repeats
+    // The naive LCM of the first steps only works if every step is a multiple of the first one
+    fn z_positions_in_cycle(
+        &self,
+        start: &Node,
+    ) -> Vec<usize> {
+        let cycle = self.ghost_cycle(start, 'Z');
+        [cycle.transient_ends, cycle.ends].concat()
+    }
+
+    fn ghost_steps(
+        &self,
+        start_suffix: char,
+        end_suffix: char,
+    ) -> Option<u64> {
+        // For each starting node
+        let nodes: Vec<_> = self
+            .nodes
+            .iter()
+            .filter_map(|(label, node)| {
+                if label.ends_with(start_suffix) {
+                    Some(node)
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        // We compute the cycle of each starting node
+        let cycles: Vec<_> = nodes
+            .iter()
+            .map(|node| self.ghost_cycle(node, end_suffix))
+            .collect();
+
+        // All ghosts may be at an ending node before one of them enters its loop
+        let mut transient_ends: Vec<_> = cycles
+            .iter()
+            .flat_map(|cycle| cycle.transient_ends.iter().copied())
+            .collect();
+        transient_ends.sort();
+        if let Some(step) = transient_ends
+            .into_iter()
+            .find(|step| cycles.iter().all(|cycle| cycle.at_end(*step)))
+        {
+            return Some(step as u64);
+        }
+
+        // Otherwise, we synchronize the loops, for every combination of their ending nodes
+        cycles
+            .iter()
+            .fold(vec![Vec::new()], |combinations, cycle| {
+                combinations
+                    .iter()
+                    .flat_map(|combination| {
+                        cycle.ends.iter().map(move |end| {
+                            let mut combination = combination.clone();
+                            combination.push((*end, cycle.length));
+                            combination
+                        })
+                    })
+                    .collect()
+            })
+            .iter()
+            .filter_map(|combination| synchronize(combination))
+            .min()
+            .map(|step| step as u64)
+    }
+}
+
+// Renders the network as in the input, nodes being sorted by label
+impl Display for Network<'_> {
+    fn fmt(
+        &self,
+        f: &mut Formatter<'_>,
+    ) -> std::fmt::Result {
+        for direction in &self.directions {
+            match direction {
+                Direction::Left => f.write_str("L")?,
+                Direction::Right => f.write_str("R")?,
+            }
+        }
+        f.write_str("\n\n")?;
+
+        let mut nodes: Vec<_> = self.nodes.values().collect();
+        nodes.sort_by_key(|node| node.label);
+        for Node { label, left, right } in nodes {
+            writeln!(f, "{} = ({}, {})", label, left, right)?;
+        }
+
+        Ok(())
+    }
 }
 
 fn first(
@@ -143,31 +351,29 @@ fn second(
     let (_, network) = Network::parse(data).finish().unwrap();
     // println!("[{}] Network: {:?}", name, network);
 
-    // For each starting node
-    let nodes: Vec<_> = network
+    let mut starts: Vec<_> = network
         .nodes
-        .iter()
-        .filter_map(|(label, node)| {
-            if label.ends_with('A') {
-                Some(node)
-            } else {
-                None
-            }
-        })
+        .values()
+        .filter(|node| node.label.ends_with('A'))
         .collect();
+    starts.sort_by_key(|node| node.label);
+    for start in starts {
+        let positions = network.z_positions_in_cycle(start);
+        let clean = positions
+            .first()
+            .is_some_and(|first| positions.iter().all(|position| position % first == 0));
+        if !clean {
+            println!(
+                "[{}] The naive LCM does not hold from {}: end nodes at {:?}",
+                name, start.label, positions
+            );
+        }
+    }
 
-    // We compute the path for each starting node to a ending node
-    let visited: Vec<Vec<&str>> = nodes
-        .iter()
-        .map(|node| network.follow_until(node, |node| node.label.ends_with('Z')))
-        .collect();
-    // println!("[{}] Visited: {:?}", name, visited);
-
-    // And then we compute the LCM to get the moment all starting nodes are at an ending node
-    let lcm = visited
-        .iter()
-        .fold(1, |lcm, visited| num::integer::lcm(lcm, visited.len() - 1));
-    println!("[{}] Step: {}", name, lcm);
+    match network.ghost_steps('A', 'Z') {
+        Some(step) => println!("[{}] Step: {}", name, step),
+        None => println!("[{}] The ghosts are never all at an end node", name),
+    }
 }
 
 pub fn run() {
@@ -177,3 +383,84 @@ pub fn run() {
     second("Second example", include_str!("data/day8/ex3")); // 6
     second("Second", include_str!("data/day8/input")); // 11 188 774 513 823
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ghost_steps_of_example() {
+        let (_, network) = Network::parse(include_str!("data/day8/ex3"))
+            .finish()
+            .unwrap();
+        assert_eq!(network.ghost_steps('A', 'Z'), Some(6));
+    }
+
+    #[test]
+    fn synchronize_offset_cycles() {
+        // Every ghost loops from its start
+        assert_eq!(synchronize(&[(4, 4), (6, 6)]), Some(12));
+        assert_eq!(synchronize(&[(8, 4), (2, 2)]), Some(8));
+
+        assert_eq!(synchronize(&[(3, 5), (4, 7)]), Some(18));
+        // 2 is the first step matching both, but the first ghost only gets there at 7
+        assert_eq!(synchronize(&[(7, 5), (2, 3)]), Some(17));
+        // Periods are not coprime
+        assert_eq!(synchronize(&[(2, 4), (4, 6)]), Some(10));
+        assert_eq!(synchronize(&[(1, 4), (2, 6)]), None);
+        assert_eq!(synchronize(&[]), None);
+    }
+
+    #[test]
+    fn ghost_steps_with_offsets() {
+        // 1A reaches 1Z every 2 steps from step 2, 2A reaches 2Z every 3 steps from step 1:
+        // the LCM of the first steps, 2, is wrong
+        let data = "L\n\n1A = (1B, 1B)\n1B = (1Z, 1Z)\n1Z = (1C, 1C)\n1C = (1Z, 1Z)\n2A = (2Z, 2Z)\n2Z = (2B, 2B)\n2B = (2C, 2C)\n2C = (2Z, 2Z)\n";
+        let (_, network) = Network::parse(data).finish().unwrap();
+        assert_eq!(network.ghost_steps('A', 'Z'), Some(4));
+
+        // Both ghosts are at an end node before 2A loops
+        let data =
+            "L\n\n1A = (1Z, 1Z)\n1Z = (1Z, 1Z)\n2A = (2Z, 2Z)\n2Z = (2B, 2B)\n2B = (2B, 2B)\n";
+        let (_, network) = Network::parse(data).finish().unwrap();
+        assert_eq!(network.ghost_steps('A', 'Z'), Some(1));
+    }
+
+    #[test]
+    fn ghost_steps_when_start_is_an_end() {
+        // The start node loops on itself and is also an end node
+        let (_, network) = Network::parse("L\n\n1A = (1A, 1A)\n").finish().unwrap();
+        assert_eq!(network.ghost_steps('A', 'A'), Some(1));
+
+        // Back to the start node after 2 steps, the other ghost being at an end every 3 steps
+        let data = "L\n\n1A = (1B, 1B)\n1B = (1A, 1A)\n2A = (2B, 2B)\n2B = (2C, 2C)\n2C = (2A, 2A)\n";
+        let (_, network) = Network::parse(data).finish().unwrap();
+        assert_eq!(network.ghost_steps('A', 'A'), Some(6));
+    }
+
+    #[test]
+    fn display_as_input() {
+        for data in [include_str!("data/day8/ex1"), include_str!("data/day8/ex2")] {
+            let (_, network) = Network::parse(data).finish().unwrap();
+            assert_eq!(network.to_string(), data);
+        }
+    }
+
+    #[test]
+    fn z_positions_of_example() {
+        let (_, network) = Network::parse(include_str!("data/day8/ex3"))
+            .finish()
+            .unwrap();
+
+        assert_eq!(
+            network.z_positions_in_cycle(network.node_for_label("11A")),
+            vec![2]
+        );
+        // The path loops every 3 steps but the directions every 2, so the full cycle sees
+        // the end node twice, each time at a multiple of the first offset
+        assert_eq!(
+            network.z_positions_in_cycle(network.node_for_label("22A")),
+            vec![3, 6]
+        );
+    }
+}
@@ -2,6 +2,7 @@ use nom::{
     character,
     character::complete::{
         line_ending,
+        space0,
         space1,
     },
     combinator::{
@@ -12,25 +13,36 @@ use nom::{
         many1,
         separated_list1,
     },
-    sequence::terminated,
+    sequence::{
+        delimited,
+        terminated,
+    },
     Finish,
     IResult,
 };
+use rayon::prelude::*;
 
+// Numbers may be separated by any mix of spaces and tabs, with some around the row as well
 fn parse(input: &str) -> IResult<&str, Vec<Vec<i64>>> {
     all_consuming(many1(terminated(
-        separated_list1(space1, character::complete::i64),
+        delimited(
+            space0,
+            separated_list1(space1, character::complete::i64),
+            space0,
+        ),
         opt(line_ending),
     )))(input)
 }
 
-fn compute_differences(sequence: &[i64]) -> Vec<Vec<i64>> {
+pub fn compute_differences(sequence: &[i64]) -> Vec<Vec<i64>> {
     let mut differences: Vec<Vec<i64>> = Vec::new();
     differences.push(sequence.to_owned());
 
     loop {
+        // A single value cannot be differentiated further, it is considered constant.
+        // This means a sequence of one value extrapolates to that same value.
         let last = differences.last().unwrap();
-        if last.iter().all(|v| *v == 0) {
+        if last.len() < 2 || last.iter().all(|v| *v == 0) {
             break;
         }
 
@@ -44,6 +56,81 @@ fn compute_differences(sequence: &[i64]) -> Vec<Vec<i64>> {
     differences
 }
 
+// Renders the differences like the puzzle does, each row shifted under the previous one
+fn render_triangle(diffs: &[Vec<i64>]) -> String {
+    let width = diffs
+        .iter()
+        .flatten()
+        .map(|value| value.to_string().len())
+        .max()
+        .unwrap_or_default();
+    // Keep the cell width even, so each row can be shifted by half a cell
+    let cell = (width + 3) / 2 * 2;
+
+    let lines: Vec<_> = diffs
+        .iter()
+        .enumerate()
+        .map(|(row, values)| {
+            let values: String = values
+                .iter()
+                .map(|value| format!("{:>cell$}", value))
+                .collect();
+            format!("{}{}", " ".repeat(row * cell / 2), values)
+        })
+        .collect();
+
+    // Drop the margin left by the first row
+    let margin = lines
+        .iter()
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or_default();
+
+    lines
+        .iter()
+        .map(|line| format!("{}\n", &line[margin..]))
+        .collect()
+}
+
+// Step of a constant (step 0) or arithmetic sequence, which can be extrapolated directly
+fn arithmetic_step(sequence: &[i64]) -> Option<i64> {
+    match sequence {
+        [] => None,
+        [_] => Some(0),
+        [first, second, ..] => {
+            let step = second - first;
+            sequence
+                .windows(2)
+                .all(|window| window[1] - window[0] == step)
+                .then_some(step)
+        }
+    }
+}
+
+fn extrapolate_next(sequence: &[i64]) -> i64 {
+    if let Some(step) = arithmetic_step(sequence) {
+        return sequence[sequence.len() - 1] + step;
+    }
+
+    compute_differences(sequence)
+        .iter()
+        .rev()
+        .fold(0_i64, |acc, differences| acc + *differences.last().unwrap())
+}
+
+fn extrapolate_previous(sequence: &[i64]) -> i64 {
+    if let Some(step) = arithmetic_step(sequence) {
+        return sequence[0] - step;
+    }
+
+    compute_differences(sequence)
+        .iter()
+        .rev()
+        .fold(0_i64, |acc, differences| {
+            *differences.first().unwrap() - acc
+        })
+}
+
 fn first(
     name: &str,
     data: &str,
@@ -51,14 +138,8 @@ fn first(
     let (_, sequences) = parse(data).finish().unwrap();
 
     let sum: i64 = sequences
-        .iter()
-        .map(|seq| compute_differences(seq.as_slice()))
-        .map(|differences| {
-            differences
-                .iter()
-                .rev()
-                .fold(0_i64, |acc, differences| acc + *differences.last().unwrap())
-        })
+        .par_iter()
+        .map(|seq| extrapolate_next(seq.as_slice()))
         .sum();
 
     println!("[{}] Sum: {}", name, sum);
@@ -71,21 +152,102 @@ fn second(
     let (_, sequences) = parse(data).finish().unwrap();
 
     let sum: i64 = sequences
-        .iter()
-        .map(|seq| compute_differences(seq.as_slice()))
-        .map(|differences| {
-            differences.iter().rev().fold(0_i64, |acc, differences| {
-                *differences.first().unwrap() - acc
-            })
-        })
+        .par_iter()
+        .map(|seq| extrapolate_previous(seq.as_slice()))
         .sum();
 
     println!("[{}] Sum: {}", name, sum);
 }
 
+fn triangle(
+    name: &str,
+    data: &str,
+    index: usize,
+) {
+    let (_, sequences) = parse(data).finish().unwrap();
+
+    let differences = compute_differences(&sequences[index]);
+    println!("[{}] Differences:\n{}", name, render_triangle(&differences));
+}
+
 pub fn run() {
     first("First example", include_str!("data/day9/ex1")); // 114
     first("First", include_str!("data/day9/input")); // 1 647 269 739
     second("Second example", include_str!("data/day9/ex1")); // 2
     second("Second", include_str!("data/day9/input")); // 864
+    triangle("Triangle example", include_str!("data/day9/ex1"), 2);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_of_differing_lengths() {
+        let data = "3 5\n1 4 9 16 25\n1 8 27 64 125 216 343 512\n";
+        let (_, sequences) = parse(data).finish().unwrap();
+
+        let next: Vec<_> = sequences.iter().map(|seq| extrapolate_next(seq)).collect();
+        assert_eq!(next, vec![7, 36, 729]);
+
+        let previous: Vec<_> = sequences
+            .iter()
+            .map(|seq| extrapolate_previous(seq))
+            .collect();
+        assert_eq!(previous, vec![1, 0, 0]);
+    }
+
+    #[test]
+    fn render_triangle_of_example() {
+        let rendered = render_triangle(&compute_differences(&[0, 3, 6, 9, 12, 15]));
+
+        assert_eq!(
+            rendered,
+            "0   3   6   9  12  15\n  3   3   3   3   3\n    0   0   0   0\n"
+        );
+    }
+
+    #[test]
+    fn arithmetic_sequences() {
+        assert_eq!(arithmetic_step(&[4, 4, 4]), Some(0));
+        assert_eq!(arithmetic_step(&[0, 3, 6, 9, 12, 15]), Some(3));
+        assert_eq!(arithmetic_step(&[1, 3, 6, 10]), None);
+
+        assert_eq!(extrapolate_next(&[0, 3, 6, 9, 12, 15]), 18);
+        assert_eq!(extrapolate_previous(&[0, 3, 6, 9, 12, 15]), -3);
+        assert_eq!(extrapolate_next(&[5, -1, -7]), -13);
+        assert_eq!(extrapolate_previous(&[4, 4, 4]), 4);
+    }
+
+    #[test]
+    fn single_value_row() {
+        assert_eq!(extrapolate_next(&[7]), 7);
+        assert_eq!(extrapolate_previous(&[7]), 7);
+    }
+
+    #[test]
+    fn parse_negatives_and_mixed_whitespace() {
+        let data = "-3 -1 1 3\n10   -2\t 5\n-7\n  1 2 \t\n";
+        let (_, sequences) = parse(data).finish().unwrap();
+
+        assert_eq!(
+            sequences,
+            vec![vec![-3, -1, 1, 3], vec![10, -2, 5], vec![-7], vec![1, 2]]
+        );
+        assert!(parse("1 - 2\n").is_err());
+        assert!(parse("1 2\n\n3 4\n").is_err());
+    }
+
+    #[test]
+    fn extrapolate_negative_sequences() {
+        // -n², then -n³, going through the differences
+        assert_eq!(extrapolate_previous(&[-1, -4, -9, -16, -25]), 0);
+        assert_eq!(extrapolate_next(&[-1, -4, -9, -16, -25]), -36);
+        assert_eq!(extrapolate_previous(&[-1, -8, -27, -64, -125]), 0);
+        assert_eq!(extrapolate_next(&[-1, -8, -27, -64, -125]), -216);
+
+        // Growing towards the past: 10, 3, -2, -5, -6, -5 has differences -7, -5, -3, -1, 1
+        assert_eq!(extrapolate_previous(&[3, -2, -5, -6, -5]), 10);
+        assert_eq!(extrapolate_previous(&[-7]), -7);
+    }
 }